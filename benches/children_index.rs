@@ -0,0 +1,85 @@
+// /api/processes 统计子进程的性能：每次刷新建立一次父进程索引后逐层查找，对比每层遍历全部进程的旧实现
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use oh_procd::process::stats::{
+    ProcInfo, build_children_index, get_child_pids_and_total_memory, get_child_pids_and_total_memory_by_scan,
+};
+use std::collections::HashMap;
+use std::hint::black_box;
+use sysinfo::Pid;
+
+struct FakeProc {
+    pid: Pid,
+    parent: Option<Pid>,
+}
+
+impl ProcInfo for FakeProc {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+    fn parent(&self) -> Option<Pid> {
+        self.parent
+    }
+    fn memory(&self) -> u64 {
+        4096
+    }
+    fn exists(&self) -> bool {
+        true
+    }
+    fn is_real(&self) -> bool {
+        true
+    }
+}
+
+// 约 3300 个系统进程，其中 managed 个被管理的进程，各有 5 个子进程，每个子进程再有 1 个子进程
+fn process_table(managed: usize) -> (HashMap<Pid, FakeProc>, Vec<Pid>) {
+    let mut table = HashMap::new();
+    let mut add = |pid: usize, parent: usize| {
+        let pid = Pid::from(pid);
+        table.insert(
+            pid,
+            FakeProc {
+                pid,
+                parent: Some(Pid::from(parent)),
+            },
+        );
+    };
+    let roots: Vec<Pid> = (0..managed).map(|i| Pid::from(10_000 + i * 100)).collect();
+    for root in &roots {
+        let root = root.as_u32() as usize;
+        add(root, 1);
+        for c in 1..=5 {
+            add(root + c, root);
+            add(root + 50 + c, root + c);
+        }
+    }
+    for other in 100..3300 {
+        add(other, 1);
+    }
+    (table, roots)
+}
+
+fn bench_children(c: &mut Criterion) {
+    let mut group = c.benchmark_group("children_usage");
+    for managed in [10, 40, 100] {
+        let (table, roots) = process_table(managed);
+        group.bench_with_input(BenchmarkId::new("index", managed), &roots, |b, roots| {
+            b.iter(|| {
+                let index = build_children_index(&table);
+                for root in roots {
+                    black_box(get_child_pids_and_total_memory(&table, &index, *root));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("scan", managed), &roots, |b, roots| {
+            b.iter(|| {
+                for root in roots {
+                    black_box(get_child_pids_and_total_memory_by_scan(&table, *root));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_children);
+criterion_main!(benches);
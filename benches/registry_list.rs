@@ -0,0 +1,38 @@
+// Registry::list 的性能：持有锁时直接生成 ProcessOut（list），对比复制每个 ProcessEntry 的旧实现（list_cloned）
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use oh_procd::config::ProcessConfig;
+use oh_procd::process::registry::Registry;
+use std::hint::black_box;
+
+// 注册 n 个不运行的进程，配置中带有环境变量和参数，接近实际的大小
+fn registry_with(n: usize) -> Registry {
+    let reg = Registry::new();
+    for i in 0..n {
+        let cfg: ProcessConfig = serde_json::from_value(serde_json::json!({
+            "name": format!("worker-{}", i),
+            "project": format!("project-{}", i % 10),
+            "cmd": "sleep",
+            "args": ["3600", "--flag", "value"],
+            "envs": (0..20).map(|j| format!("ENV_{}=value-{}", j, j)).collect::<Vec<_>>(),
+            "description": "benchmark process",
+        }))
+        .unwrap();
+        reg.register_idle(cfg);
+    }
+    reg
+}
+
+fn bench_list(c: &mut Criterion) {
+    let mut group = c.benchmark_group("registry_list");
+    for n in [100, 300, 1000] {
+        let reg = registry_with(n);
+        group.bench_with_input(BenchmarkId::new("list", n), &reg, |b, reg| b.iter(|| black_box(reg.list())));
+        group.bench_with_input(BenchmarkId::new("list_cloned", n), &reg, |b, reg| {
+            b.iter(|| black_box(reg.list_cloned()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_list);
+criterion_main!(benches);
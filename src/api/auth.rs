@@ -1,6 +1,7 @@
-use crate::config::Config;
+use crate::config::{AuthUser, Config};
+use crate::process::registry::Registry;
 use axum::{
-    extract::{ConnectInfo, Extension},
+    extract::{ConnectInfo, Extension, MatchedPath, RawPathParams},
     http::{StatusCode, header},
     middleware::Next,
     response::IntoResponse,
@@ -23,6 +24,12 @@ pub struct AuthState {
 const FAILURE_WINDOW: Duration = Duration::from_secs(2 * 60); // 失败统计周期
 const MAX_FAILURES: usize = 10; //单个 IP 最多失败数
 
+impl Default for AuthState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AuthState {
     pub fn new() -> Self {
         Self {
@@ -46,16 +53,95 @@ impl AuthState {
     }
 }
 
+// 当前请求可以访问的进程范围，由 basic_auth 放入请求的 extensions
+#[derive(Clone, Debug)]
+pub enum AccessScope {
+    All,               // 未启用认证，或使用主账号
+    Limited(AuthUser), // auth.users 中的受限账号
+}
+
+impl AccessScope {
+    pub fn allows(&self, name: &str, project: Option<&str>) -> bool {
+        match self {
+            AccessScope::All => true,
+            AccessScope::Limited(user) => user.allows(name, project),
+        }
+    }
+}
+
+// 只有主账号可以访问的接口：reload 会影响全部进程，procd 日志中含有所有进程的输出
+// status 汇总了全部进程的数量和内存，self/metrics 是 procd 自身的资源使用，都不区分访问范围
+const ADMIN_ONLY_PATHS: [&str; 5] = [
+    "/api/reload",
+    "/api/reload/last",
+    "/api/logs",
+    "/api/status",
+    "/api/self/metrics",
+];
+
+// 受限账号访问范围外的进程或只有主账号可用的接口时返回 403；进程不存在时交给 handler 返回 404
+// 作为 route_layer 添加，路由匹配后执行，才能取到路径和 {name} 参数
+pub async fn check_scope(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(scope): Extension<AccessScope>,
+    path: MatchedPath,
+    params: RawPathParams,
+    req: axum::http::Request<axum::body::Body>,
+    next: Next,
+) -> impl IntoResponse {
+    let AccessScope::Limited(user) = &scope else {
+        return next.run(req).await;
+    };
+    if ADMIN_ONLY_PATHS.contains(&path.as_str()) {
+        tracing::warn!(
+            user = user.username,
+            "{} is only allowed for the admin account",
+            path.as_str()
+        );
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    if let Some((_, name)) = params.iter().find(|(k, _)| *k == "name")
+        && let Some(pe) = reg.find(name)
+        && !scope.allows(name, pe.cmd.project.as_deref())
+    {
+        tracing::warn!(user = user.username, "process {} is out of scope", name);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    next.run(req).await
+}
+
+// 检查配置的 required_header，缺失或不匹配时返回 403，在 basic_auth 之前执行
+pub async fn require_header(
+    Extension(cfg): Extension<Arc<Config>>,
+    req: axum::http::Request<axum::body::Body>,
+    next: Next,
+) -> impl IntoResponse {
+    let Some(required) = &cfg.http.required_header else {
+        return next.run(req).await;
+    };
+
+    let matched = req
+        .headers()
+        .get(required.name.as_str())
+        .is_some_and(|v| v.as_bytes() == required.value.as_bytes());
+    if !matched {
+        tracing::warn!("required header {} missing or mismatched", required.name);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    next.run(req).await
+}
+
 pub async fn basic_auth(
     Extension(cfg): Extension<Arc<Config>>,
     Extension(state): Extension<AuthState>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    req: axum::http::Request<axum::body::Body>,
+    mut req: axum::http::Request<axum::body::Body>,
     next: Next,
 ) -> impl IntoResponse {
     let cfg = cfg.as_ref();
-    if cfg.auth.username.is_empty() {
+    if !cfg.auth.enabled() {
         // 没有配置，则不检查
+        req.extensions_mut().insert(AccessScope::All);
         return next.run(req).await;
     }
 
@@ -85,16 +171,19 @@ pub async fn basic_auth(
             s.split_once(':')
                 .map(|(user, pass)| (user.to_string(), pass.to_string()))
         })
-        .map(|(user, pass)| {
+        .and_then(|(user, pass)| {
             if cfg.auth.check(&user, &pass) {
-                return true;
+                return Some(AccessScope::All);
+            }
+            if let Some(u) = cfg.auth.find_user(&user, &pass) {
+                return Some(AccessScope::Limited(u.clone()));
             }
             tracing::warn!(user = user, pass = pass, "login failed");
-            return false;
-        })
-        .unwrap_or(false);
+            None
+        });
 
-    if authorized {
+    if let Some(scope) = authorized {
+        req.extensions_mut().insert(scope);
         next.run(req).await
     } else {
         tracing::warn!("auth failed");
@@ -3,17 +3,29 @@ use axum::{
     extract::{self, ConnectInfo, Extension, Request},
     http::header,
     middleware, response,
-    routing::{get, post},
+    routing::{MethodRouter, get, post},
 };
 
 use rand::RngExt;
 use serde::Serialize;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::services::ServeDir;
+use tower_http::timeout::{RequestBodyTimeoutLayer, ResponseBodyTimeoutLayer, TimeoutLayer};
 use tower_http::trace::TraceLayer;
 
-use crate::api::auth::basic_auth;
-use crate::process::registry::{ControlMsg, ProcState, ProcessOut, Registry};
+use crate::api::auth::{AccessScope, basic_auth, check_scope, require_header};
+use crate::api::logfiles;
+use crate::config::Config;
+use crate::process::registry::{ControlMsg, ProcState, ProcessOut, Registry, StatusSummary};
+use crate::process::reload::{ProcessReload, Reloader};
+use crate::process::stats::{
+    SelfMetrics, SelfSample, build_children_index, get_child_pids_and_total_memory, listen_ports, listening_sockets,
+    self_usage,
+};
 
 const INDEX_HTML: &str = include_str!("asset/index.html");
 
@@ -44,61 +56,55 @@ struct ServerInfo {
     sys_used_swap: String,
 }
 
-use std::collections::HashMap;
 use std::fs;
-use sysinfo::Pid;
-
-/// 判断 PID 是否是进程组 leader（即排除线程）
-fn is_real_process(pid: Pid) -> bool {
-    // 读取 /proc/[pid]/status 的 Tgid 字段
-    let path = format!("/proc/{}/status", pid);
-    if let Ok(content) = fs::read_to_string(path) {
-        for line in content.lines() {
-            if !line.starts_with("Tgid:") {
-                continue;
-            }
-            if let Some(tgid_str) = line.split_whitespace().nth(1) {
-                if let Ok(tgid) = tgid_str.parse::<i32>() {
-                    return tgid == pid.as_u32() as i32;
-                }
-            }
-        }
-    }
-    // 如果无法读取，保守处理为 false
-    false
-}
 
-/// 获取父进程 pid 的所有子进程 PID 列表和总内存（KB）
-/// 返回 (Vec<Pid>, total_memory)
-fn get_child_pids_and_total_memory(processes: &HashMap<Pid, sysinfo::Process>, parent_pid: Pid) -> (Vec<Pid>, u64) {
-    let mut pids = Vec::new();
-    let mut total_memory = 0;
+/// 读取进程实际生效的用户和用户组（/proc/[pid]/status 的 Uid、Gid 字段中的 effective 值），并解析为名称
+#[cfg(unix)]
+fn effective_user_group(pid: u32) -> Option<(String, String)> {
+    use nix::unistd::{Gid, Group, Uid, User};
 
-    // 找出直接子进程
-    for proc in processes.values() {
-        if proc.parent() != Some(parent_pid) {
-            continue;
-        }
-        if !proc.exists() {
-            continue;
-        }
-        if !is_real_process(proc.pid()) {
-            continue;
-        }
-        pids.push(proc.pid());
-        total_memory += proc.memory();
+    let content = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let effective_id = |key: &str| -> Option<u32> {
+        let line = content.lines().find(|l| l.starts_with(key))?;
+        line.split_whitespace().nth(2)?.parse().ok()
+    };
+    let uid = effective_id("Uid:")?;
+    let gid = effective_id("Gid:")?;
+
+    let user = User::from_uid(Uid::from_raw(uid))
+        .ok()
+        .flatten()
+        .map(|u| u.name)
+        .unwrap_or_else(|| uid.to_string());
+    let group = Group::from_gid(Gid::from_raw(gid))
+        .ok()
+        .flatten()
+        .map(|g| g.name)
+        .unwrap_or_else(|| gid.to_string());
+    Some((user, group))
+}
 
-        // 递归获取孙子进程
-        let (child_pids, child_memory) = get_child_pids_and_total_memory(processes, proc.pid());
-        pids.extend(child_pids);
-        total_memory += child_memory;
-    }
+#[cfg(windows)]
+fn effective_user_group(_pid: u32) -> Option<(String, String)> {
+    None
+}
 
-    (pids, total_memory)
+#[derive(serde::Deserialize)]
+struct ListQuery {
+    project: Option<String>, // 只返回该项目的进程
 }
 
-async fn list_processes(Extension(reg): Extension<Arc<Registry>>, req: Request) -> Json<ListResponse<Vec<ProcessOut>>> {
-    let mut sys = sysinfo::System::new();
+// 保留上次刷新的结果，进程的 cpu_usage 需要根据两次刷新之间的差值计算
+static SYSTEM: LazyLock<Mutex<sysinfo::System>> = LazyLock::new(|| Mutex::new(sysinfo::System::new()));
+
+// 获取进程列表，并填充内存、CPU、子进程、运行用户等系统信息
+fn collect_processes(
+    reg: &Registry,
+    scope: &AccessScope,
+    project: Option<&str>,
+    hostname: &str,
+) -> (Vec<ProcessOut>, ServerInfo) {
+    let mut sys = SYSTEM.lock().unwrap();
     sys.refresh_memory();
 
     sys.refresh_processes(sysinfo::ProcessesToUpdate::All, true);
@@ -118,144 +124,656 @@ async fn list_processes(Extension(reg): Extension<Arc<Registry>>, req: Request)
         sys_used_swap: format!("{:.1} MB", (sys.used_swap() as f64) / 1024.0 / 1024.0),
     };
 
-    if let Some(proc) = sys.process(sysinfo::get_current_pid().unwrap()) {
-        server.self_memory = format!("{:.1} MB", (proc.memory() as f64) / 1024.0 / 1024.0);
-        server.self_cpu_usage = proc.cpu_usage();
-        server.pid = proc.pid().as_u32();
+    if let Some((pid, memory, cpu_usage)) = self_usage(&sys) {
+        server.self_memory = format!("{:.1} MB", (memory as f64) / 1024.0 / 1024.0);
+        server.self_cpu_usage = cpu_usage;
+        server.pid = pid;
     }
 
-    let host = req
-        .headers()
-        .get(header::HOST)
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    let hostname = host.split(':').next().unwrap_or("");
-
     let mut items = reg.list();
+    items.retain(|x| scope.allows(&x.name, x.project.as_deref()));
+    if let Some(project) = project {
+        items.retain(|x| x.project.as_deref().unwrap_or("") == project);
+    }
+    let children = build_children_index(processes);
+    let sockets = listening_sockets();
     let mut all_memory: u64 = 0;
     for x in items.iter_mut() {
         if x.pid == 0 {
             continue;
         }
         let parent_pid = sysinfo::Pid::from_u32(x.pid);
-        let (child_pids, total_memory) = get_child_pids_and_total_memory(processes, parent_pid);
+        let (child_pids, total_memory) = get_child_pids_and_total_memory(processes, &children, parent_pid);
 
         let mut total_memory = total_memory;
+        let mut cpu_usage: f32 = child_pids
+            .iter()
+            .filter_map(|p| processes.get(p))
+            .fold(0.0, |acc, p| acc + p.cpu_usage());
         if let Some(proc) = sys.process(sysinfo::Pid::from_u32(x.pid)) {
             total_memory += proc.memory();
+            cpu_usage += proc.cpu_usage();
         }
         all_memory += total_memory;
+        x.cpu_usage = cpu_usage;
 
         if total_memory > 0 {
             x.memory_used = format!("{:.1} MB", (total_memory as f64) / 1024.0 / 1024.0);
         }
 
-        if child_pids.len() > 0 {
+        if !child_pids.is_empty() {
             x.child_pids = child_pids.iter().map(|p| p.as_u32()).collect();
             x.child_pids.sort();
         }
 
+        let mut pids = x.child_pids.clone();
+        pids.push(x.pid);
+        x.listen_ports = listen_ports(&sockets, &pids);
+
+        if let Some((user, group)) = effective_user_group(x.pid) {
+            x.run_user = Some(user);
+            x.run_group = Some(group);
+        }
+
         if x.web_address.contains("{") {
-            x.web_address = x.web_address.replace("{HOST}", hostname);
+            x.web_address = render_web_address(x, hostname);
         }
     }
 
     server.used_memory = format!("{:.1} MB", (all_memory as f64) / 1024.0 / 1024.0);
+    (items, server)
+}
+
+// 替换 web_address 中的变量，{PORT} 未配置 port 时使用检测到的第一个监听端口
+// {PORT}、{PID} 没有值时（端口未知、进程未运行）返回空，页面上不显示链接
+fn render_web_address(x: &ProcessOut, hostname: &str) -> String {
+    let mut addr = x.web_address.replace("{HOST}", hostname).replace("{NAME}", &x.name);
+    if addr.contains("{PORT}") {
+        let Some(port) = x.cmd.port.or(x.listen_ports.first().copied()) else {
+            return String::new();
+        };
+        addr = addr.replace("{PORT}", &port.to_string());
+    }
+    if addr.contains("{PID}") {
+        if x.pid == 0 {
+            return String::new();
+        }
+        addr = addr.replace("{PID}", &x.pid.to_string());
+    }
+    addr
+}
+
+fn request_hostname(req: &Request) -> String {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    host.split(':').next().unwrap_or("").to_string()
+}
+
+async fn list_processes(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(scope): Extension<AccessScope>,
+    extract::Query(query): extract::Query<ListQuery>,
+    req: Request,
+) -> Json<ListResponse<Vec<ProcessOut>>> {
+    let (items, server) = collect_processes(&reg, &scope, query.project.as_deref(), &request_hostname(&req));
 
     let val: ListResponse<Vec<ProcessOut>> = ListResponse {
         code: 0,
         message: "success".to_string(),
         data: items,
-        server: server,
+        server,
     };
 
     Json(val)
 }
 
+// CSV 字段中含有逗号、引号或换行时需要用引号包起来
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// 以 CSV 格式导出进程列表，便于导入表格工具
+async fn list_processes_csv(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(scope): Extension<AccessScope>,
+    extract::Query(query): extract::Query<ListQuery>,
+    req: Request,
+) -> impl response::IntoResponse {
+    let (items, _) = collect_processes(&reg, &scope, query.project.as_deref(), &request_hostname(&req));
+
+    let mut csv = String::from("name,state,pid,start_time,start_count,memory_used,cpu_usage,uptime\n");
+    for x in &items {
+        let row = [
+            csv_field(&x.name),
+            csv_field(&x.state.to_string()),
+            x.pid.to_string(),
+            csv_field(x.start_time.as_deref().unwrap_or("")),
+            x.start_count.to_string(),
+            csv_field(&x.memory_used),
+            format!("{:.1}", x.cpu_usage),
+            x.uptime_secs.to_string(),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    ([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv)
+}
+
+async fn process_detail(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+) -> response::Response {
+    use response::IntoResponse;
+    match reg.detail(&name) {
+        Some(detail) => Json(detail).into_response(),
+        None => (axum::http::StatusCode::NOT_FOUND, "process not found").into_response(),
+    }
+}
+
+// 向进程的 supervise 循环发送控制命令
+// 进程被 kill 或未启用时，supervise 循环已结束，channel 已关闭，此时返回 409 而不是发送失败
+async fn send_control(reg: &Registry, name: &str, msg: ControlMsg, action: &str) -> (axum::http::StatusCode, String) {
+    let Some(tx) = reg.get_control(name) else {
+        return (axum::http::StatusCode::NOT_FOUND, "process not found".to_string());
+    };
+    if tx.is_closed() {
+        return (
+            axum::http::StatusCode::CONFLICT,
+            "process is not supervised, start it first".to_string(),
+        );
+    }
+
+    // Stopping 状态由 supervise 循环在真正开始停止进程时设置
+    if let Err(e) = tx.send(msg).await {
+        tracing::error!("failed to send {} to {}: {}", action, name, e);
+        return (
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to {} process", action),
+        );
+    }
+    (axum::http::StatusCode::OK, format!("{} signal sent", action))
+}
+
 async fn restart_process(
     Extension(reg): Extension<Arc<Registry>>,
     extract::Path(name): extract::Path<String>,
 ) -> impl response::IntoResponse {
     tracing::info!("Restarting process: {}", name);
-    reg.set_state(&name, ProcState::Stopping);
-    let reg = reg.as_ref();
-
-    match reg.get_control(&name) {
-        Some(tx) => {
-            if let Err(e) = tx.send(ControlMsg::Restart).await {
-                tracing::error!("failed to send restart to {}: {}", name, e);
-                (
-                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    "failed to restart process",
-                )
-            } else {
-                (axum::http::StatusCode::OK, "restart signal sent")
-            }
-        }
-        None => (axum::http::StatusCode::NOT_FOUND, "process not found"),
-    }
+    send_control(&reg, &name, ControlMsg::Restart("restart"), "restart").await
 }
+
 async fn kill_process(
     Extension(reg): Extension<Arc<Registry>>,
     extract::Path(name): extract::Path<String>,
 ) -> impl response::IntoResponse {
     tracing::info!("Killing process: {}", name);
-    reg.set_state(&name, ProcState::Stopping);
-    let reg = reg.as_ref();
-
-    match reg.get_control(&name) {
-        Some(tx) => {
-            if let Err(e) = tx.send(ControlMsg::Kill).await {
-                tracing::error!("failed to send restart to {}: {}", name, e);
-                (
-                    axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                    "failed to restart process",
-                )
-            } else {
-                (axum::http::StatusCode::OK, "restart signal sent")
+    // 幂等：supervise 循环已结束（已被 kill、未启动或 enable=false）时进程已经停止，直接返回成功
+    if reg.get_control(&name).is_some_and(|tx| tx.is_closed()) {
+        return (axum::http::StatusCode::OK, "process is already stopped".to_string());
+    }
+    send_control(&reg, &name, ControlMsg::Kill, "kill").await
+}
+
+#[derive(serde::Deserialize)]
+struct ReloadQuery {
+    strategy: Option<String>, // signal（默认，发送 SIGHUP）或 blue-green
+}
+
+#[cfg(unix)]
+async fn reload_process(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(query): extract::Query<ReloadQuery>,
+) -> impl response::IntoResponse {
+    tracing::info!("Reloading process: {} (strategy={:?})", name, query.strategy);
+    let Some(pe) = reg.find(&name) else {
+        return (axum::http::StatusCode::NOT_FOUND, "process not found".to_string());
+    };
+    if pe.state != ProcState::Running {
+        return (axum::http::StatusCode::CONFLICT, "process is not running".to_string());
+    }
+
+    match query.strategy.as_deref() {
+        None | Some("signal") => {}
+        Some("blue-green") => {
+            // 需要 ready_file 判断新实例是否就绪，结果异步生效，可通过进程的 pid 和 stop_reason 查看
+            if pe.cmd.ready_file.is_none() {
+                return (
+                    axum::http::StatusCode::BAD_REQUEST,
+                    "blue-green reload requires ready_file".to_string(),
+                );
             }
+            return send_control(&reg, &name, ControlMsg::BlueGreen, "blue-green reload").await;
+        }
+        Some(s) => {
+            return (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("unknown strategy {:?}, expected signal or blue-green", s),
+            );
+        }
+    }
+
+    match crate::process::supervisor::reload_process(pe.pid.unwrap_or(0)) {
+        Ok(()) => {
+            reg.set_reloaded(&name);
+            (axum::http::StatusCode::OK, "reload signal sent".to_string())
+        }
+        Err(e) => {
+            tracing::error!("failed to send reload to {}: {}", name, e);
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to reload process: {}", e),
+            )
+        }
+    }
+}
+
+#[cfg(windows)]
+async fn reload_process() -> impl response::IntoResponse {
+    (
+        axum::http::StatusCode::NOT_IMPLEMENTED,
+        "reload is not supported on windows",
+    )
+}
+
+#[derive(serde::Deserialize)]
+struct LogLevelQuery {
+    level: String,
+}
+
+// 运行时修改子进程输出写入日志的级别，不需要重启进程
+async fn set_log_level(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(query): extract::Query<LogLevelQuery>,
+) -> impl response::IntoResponse {
+    let Ok(level) = query.level.parse::<tracing::Level>() else {
+        return (axum::http::StatusCode::BAD_REQUEST, "invalid level");
+    };
+    match reg.find(&name) {
+        Some(pe) => {
+            tracing::info!("set log_level of {} to {}", name, level);
+            pe.output.level.set(level);
+            (axum::http::StatusCode::OK, "log level updated")
         }
         None => (axum::http::StatusCode::NOT_FOUND, "process not found"),
     }
 }
+
 async fn start_process(
     Extension(reg): Extension<Arc<Registry>>,
     extract::Path(name): extract::Path<String>,
 ) -> impl response::IntoResponse {
     tracing::info!("Starting process: {}", name);
-    reg.set_state(&name, ProcState::Ready);
 
     match reg.as_ref().find(&name) {
         Some(pe) => {
-            pe.cmd.clone().start_spawn(reg);
+            // 幂等：运行中的进程已有 supervise 循环在管理，不再启动，否则会出现两个循环争抢同一个进程
+            if matches!(pe.state, ProcState::Starting | ProcState::Running) {
+                return (axum::http::StatusCode::OK, "process is already running");
+            }
+            // 正在停止的进程等停止完成后才能再启动
+            if pe.state == ProcState::Stopping {
+                return (axum::http::StatusCode::CONFLICT, "process is stopping, retry later");
+            }
+            // 进程已退出但 supervise 循环仍在（等待 next 后自动重启、启动失败重试中），同样不需要再启动
+            if !pe.control_tx.is_closed() {
+                return (
+                    axum::http::StatusCode::OK,
+                    "process is already supervised and will restart by itself",
+                );
+            }
+            // 检查和注册之间可能有并发的启动请求，以 register_process 的结果为准
+            let Some(rx) = reg.register_process(&name, pe.cmd.clone()) else {
+                return (axum::http::StatusCode::OK, "process is already running");
+            };
+            reg.set_state(&name, ProcState::Ready);
+            tokio::spawn(crate::process::supervisor::supervise_registered(pe.cmd, reg, rx));
             (axum::http::StatusCode::OK, "start signal sent")
         }
         None => (axum::http::StatusCode::NOT_FOUND, "process not found"),
     }
 }
 
+// 清除自动重启的计数并重新启动进程，用于达到 max_restarts 后停在 Error 的进程，问题修复后恢复运行
+// supervise 循环仍在运行时只清除计数，不打断当前的进程
+async fn reset_process(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+) -> impl response::IntoResponse {
+    tracing::info!("Resetting process: {}", name);
+    let Some(pe) = reg.find(&name) else {
+        return (axum::http::StatusCode::NOT_FOUND, "process not found");
+    };
+    reg.reset_restarts(&name);
+    if !pe.control_tx.is_closed() {
+        return (
+            axum::http::StatusCode::OK,
+            "restart counters reset, process is still supervised",
+        );
+    }
+    let Some(rx) = reg.register_process(&name, pe.cmd.clone()) else {
+        return (
+            axum::http::StatusCode::OK,
+            "restart counters reset, process is already running",
+        );
+    };
+    reg.set_state(&name, ProcState::Ready);
+    tokio::spawn(crate::process::supervisor::supervise_registered(pe.cmd, reg, rx));
+    (axum::http::StatusCode::OK, "restart counters reset, starting process")
+}
+
+// 重新加载配置文件，返回新增、删除、重启的进程
+async fn reload_config(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(reloader): Extension<Arc<Reloader>>,
+) -> response::Response {
+    use response::IntoResponse;
+    // 在单独的任务中执行，请求超时或客户端断开时 reload 不会停在一半（旧进程已停止、新进程未启动）
+    let task = tokio::spawn(async move { reloader.reload(&reg).await });
+    let Ok(result) = task.await else {
+        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "reload task failed").into_response();
+    };
+    match result {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => {
+            tracing::error!("reload config failed: {:#}", e);
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("failed to reload config: {:#}", e),
+            )
+                .into_response()
+        }
+    }
+}
+
+// 只重新加载一个进程的配置，返回变化的字段和处理结果
+// check_scope 只能检查已注册的进程，配置文件中新增的进程和修改后的 project 由 reload_process 按新配置检查
+async fn reload_process_config(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(reloader): Extension<Arc<Reloader>>,
+    Extension(scope): Extension<AccessScope>,
+    extract::Path(name): extract::Path<String>,
+) -> response::Response {
+    use response::IntoResponse;
+    tracing::info!("Reloading config of process: {}", name);
+    // 和 reload_config 一样在单独的任务中执行，避免停在一半
+    let task = tokio::spawn(async move {
+        reloader
+            .reload_process(&reg, &name, |p| scope.allows(&p.name, p.project.as_deref()))
+            .await
+    });
+    let Ok(result) = task.await else {
+        return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "reload task failed").into_response();
+    };
+    match result {
+        Ok(ProcessReload::Done(result)) => Json(result).into_response(),
+        Ok(ProcessReload::NotFound) => {
+            (axum::http::StatusCode::NOT_FOUND, "process not found in config file").into_response()
+        }
+        Ok(ProcessReload::Forbidden) => (axum::http::StatusCode::FORBIDDEN, "process is out of scope").into_response(),
+        Err(e) => {
+            tracing::error!("reload process config failed: {:#}", e);
+            (
+                axum::http::StatusCode::BAD_REQUEST,
+                format!("failed to reload config: {:#}", e),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn last_reload(Extension(reloader): Extension<Arc<Reloader>>) -> response::Response {
+    use response::IntoResponse;
+    match reloader.last() {
+        Some(result) => Json(result).into_response(),
+        None => (axum::http::StatusCode::NOT_FOUND, "config has not been reloaded").into_response(),
+    }
+}
+
+const MAX_WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3600); // /api/wait 最长等待时间
+
+#[derive(serde::Deserialize)]
+struct WaitQuery {
+    names: String, // 逗号分隔的进程名称
+    #[serde(default = "default_wait_timeout", with = "humantime_serde")]
+    timeout: std::time::Duration, // 最长等待时间，默认 30s，最大 1h
+}
+
+fn default_wait_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(30)
+}
+
+// 等待一组进程全部进入 Running，用于部署脚本编排启动顺序；全部就绪返回 200，超时返回 408 和各进程的情况
+async fn wait_processes(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(scope): Extension<AccessScope>,
+    extract::Query(q): extract::Query<WaitQuery>,
+) -> response::Response {
+    use response::IntoResponse;
+    let names: Vec<String> = q
+        .names
+        .split(',')
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect();
+    if names.is_empty() {
+        return (axum::http::StatusCode::BAD_REQUEST, "names must not be empty").into_response();
+    }
+    for name in &names {
+        let Some(pe) = reg.find(name) else {
+            return (axum::http::StatusCode::NOT_FOUND, format!("process {} not found", name)).into_response();
+        };
+        if !scope.allows(name, pe.cmd.project.as_deref()) {
+            return (
+                axum::http::StatusCode::FORBIDDEN,
+                format!("process {} is out of scope", name),
+            )
+                .into_response();
+        }
+    }
+
+    let result = reg.wait_running(&names, q.timeout.min(MAX_WAIT_TIMEOUT)).await;
+    let status = if result.ok {
+        axum::http::StatusCode::OK
+    } else {
+        axum::http::StatusCode::REQUEST_TIMEOUT
+    };
+    (status, Json(result)).into_response()
+}
+
+// 简要的运行状态，不刷新进程信息，适合外部监控频繁轮询
+async fn status(Extension(reg): Extension<Arc<Registry>>) -> Json<StatusSummary> {
+    Json(reg.summary())
+}
+
+#[derive(Serialize)]
+struct SelfMetricsOut {
+    interval_secs: u64,
+    samples: Vec<SelfSample>, // 按时间从旧到新
+}
+
+// procd 自身内存和 CPU 使用的历史
+async fn self_metrics(Extension(metrics): Extension<Arc<SelfMetrics>>) -> Json<SelfMetricsOut> {
+    Json(SelfMetricsOut {
+        interval_secs: metrics.interval_secs(),
+        samples: metrics.samples(),
+    })
+}
+
 async fn logs(Extension(lb): Extension<crate::logger::LogBuffer>) -> Json<Vec<String>> {
     let mut lines = lb.get_logs();
     lines.reverse();
     Json(lines)
 }
 
-pub fn build_router() -> Router {
-    Router::new()
-        .route("/", get(index))
+const DASHBOARD_LOGS: usize = 50; // dashboard 默认返回的日志行数
+
+#[derive(serde::Deserialize)]
+struct DashboardQuery {
+    project: Option<String>, // 只返回该项目的进程
+    logs: Option<usize>,     // 返回的日志行数，默认 DASHBOARD_LOGS，0 表示不返回日志
+}
+
+// 内置页面一次请求需要的全部数据
+#[derive(Serialize)]
+struct Dashboard {
+    processes: Vec<ProcessOut>,
+    server: ServerInfo,
+    logs: Vec<String>, // procd 自身最近的日志，最新的在前；受限账号不能查看日志，始终为空
+    read_only: bool,   // 只读模式，页面据此隐藏操作按钮
+}
+
+// dashboard 合并 /api/processes、/api/logs 的数据，页面刷新时只需要一次请求
+async fn dashboard(
+    Extension(reg): Extension<Arc<Registry>>,
+    Extension(scope): Extension<AccessScope>,
+    Extension(lb): Extension<crate::logger::LogBuffer>,
+    Extension(cfg): Extension<Arc<Config>>,
+    extract::Query(query): extract::Query<DashboardQuery>,
+    req: Request,
+) -> Json<Dashboard> {
+    let (processes, server) = collect_processes(&reg, &scope, query.project.as_deref(), &request_hostname(&req));
+
+    let logs = match scope {
+        AccessScope::All => {
+            let mut lines = lb.get_logs();
+            let keep = query.logs.unwrap_or(DASHBOARD_LOGS);
+            lines.drain(..lines.len().saturating_sub(keep));
+            lines.reverse();
+            lines
+        }
+        AccessScope::Limited(_) => vec![],
+    };
+
+    Json(Dashboard {
+        processes,
+        server,
+        logs,
+        read_only: cfg.http.read_only,
+    })
+}
+
+// 根据配置的来源列表构建 CORS layer，列表为空时返回 None（仅允许同源访问）
+fn cors_layer(origins: &[String]) -> Option<CorsLayer> {
+    let origins: Vec<header::HeaderValue> = origins
+        .iter()
+        .filter_map(|o| match header::HeaderValue::from_str(o.trim()) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                tracing::warn!("invalid cors origin {:?}: {:?}, skipped", o, e);
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        return None;
+    }
+
+    tracing::info!("cors enabled for {:?}", origins);
+    let layer = CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+        .allow_credentials(true); // 支持 basic auth
+    Some(layer)
+}
+
+// 只读模式下代替修改状态的接口
+async fn read_only_rejected() -> impl response::IntoResponse {
+    (
+        axum::http::StatusCode::METHOD_NOT_ALLOWED,
+        "procd is in read-only mode (http.read_only)",
+    )
+}
+
+pub fn build_router(cfg: &Config) -> Router {
+    // 修改状态的接口：只读模式下仍然注册路径，统一返回 405，而不是 404
+    let write = |route: MethodRouter| match cfg.http.read_only {
+        true => post(read_only_rejected),
+        false => route,
+    };
+    if cfg.http.read_only {
+        tracing::info!("http.read_only is enabled, state-changing APIs are disabled");
+    }
+
+    let mut router = Router::new()
         .route("/api/logs", get(logs))
+        .route("/api/reload", write(post(reload_config)))
+        .route("/api/reload/last", get(last_reload))
+        .route("/api/self/metrics", get(self_metrics))
+        .route("/api/status", get(status))
+        .route("/api/dashboard", get(dashboard))
         .route("/api/processes", get(list_processes))
-        .route("/api/process/{name}/restart", post(restart_process))
-        .route("/api/process/{name}/kill", post(kill_process))
-        .route("/api/process/{name}/start", post(start_process))
-        .layer(middleware::from_fn(basic_auth))
-        .layer(TraceLayer::new_for_http().make_span_with(|req: &Request<_>| {
-            let client_addr = req
-                .extensions()
-                .get::<ConnectInfo<SocketAddr>>()
-                .map(|ci| ci.0.to_string())
-                .unwrap_or_else(|| "unknown".to_string());
-            let log_id: u64 = rand::rng().random_range(1..9999999);
-            tracing::info_span!("HTTP", log_id = log_id, client = client_addr)
-        }))
+        .route("/api/processes.csv", get(list_processes_csv))
+        .route("/api/process/{name}", get(process_detail))
+        .route("/api/process/{name}/restart", write(post(restart_process)))
+        .route("/api/process/{name}/kill", write(post(kill_process)))
+        .route("/api/process/{name}/start", write(post(start_process)))
+        .route("/api/process/{name}/reset", write(post(reset_process)))
+        .route("/api/process/{name}/reload", write(post(reload_process)))
+        .route("/api/process/{name}/reload-config", write(post(reload_process_config)))
+        .route("/api/process/{name}/log_level", write(post(set_log_level)))
+        .route("/api/process/{name}/logfiles", get(logfiles::list_logfiles))
+        .route("/api/process/{name}/logfile/{filename}", get(logfiles::get_logfile))
+        .route("/api/process/{name}/logs/merged", get(logfiles::merged_logfile));
+
+    // 超时只作用于普通接口，之后添加的 /api/wait、SSE 等长连接接口不受限制
+    if let Some(timeout) = cfg.http.request_timeout {
+        router = router.layer(TimeoutLayer::with_status_code(
+            axum::http::StatusCode::REQUEST_TIMEOUT,
+            timeout,
+        ));
+    }
+    if let Some(timeout) = cfg.http.read_timeout {
+        router = router.layer(RequestBodyTimeoutLayer::new(timeout));
+    }
+    if let Some(timeout) = cfg.http.write_timeout {
+        router = router.layer(ResponseBodyTimeoutLayer::new(timeout));
+    }
+    router = router
+        .route("/api/wait", get(wait_processes))
+        .route("/api/process/{name}/tail", get(logfiles::tail_logfile));
+    // 在全部 API 路由之后添加，对静态资源不生效
+    router = router.route_layer(middleware::from_fn(check_scope));
+
+    // 配置了 web_root 时，从该目录提供静态资源；目录中没有 index.html 时仍使用内置页面
+    let web_root = cfg.http.web_root.trim();
+    if web_root.is_empty() || !Path::new(web_root).join("index.html").exists() {
+        router = router.route("/", get(index));
+    }
+    if !web_root.is_empty() {
+        tracing::info!("serving static assets from {}", web_root);
+        router = router.fallback_service(ServeDir::new(web_root));
+    }
+
+    // auth 在最后添加，对静态资源同样生效
+    router = router.layer(middleware::from_fn(basic_auth));
+    // 后添加的 layer 先执行，required_header 在 basic_auth 之前检查
+    router = router.layer(middleware::from_fn(require_header));
+
+    // 按客户端的 Accept-Encoding 压缩响应；默认的判断规则会跳过 text/event-stream 等流式响应和很小的响应
+    router = router.layer(CompressionLayer::new());
+
+    // CORS 需要在 auth 外层，否则浏览器的预检请求（OPTIONS）会被 401 拦截
+    if let Some(cors) = cors_layer(&cfg.http.cors_origins) {
+        router = router.layer(cors);
+    }
+
+    router.layer(TraceLayer::new_for_http().make_span_with(|req: &Request<_>| {
+        let client_addr = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ci| ci.0.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let log_id: u64 = rand::rng().random_range(1..9999999);
+        tracing::info_span!("HTTP", log_id = log_id, client = client_addr)
+    }))
 }
@@ -0,0 +1,406 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{self, Extension},
+    http::{StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio::time::Duration;
+use tokio_util::io::{ReaderStream, SyncIoBridge};
+
+use crate::config::LogTimezone;
+use crate::process::logger::{current_hour, current_log_file_name, parse_seq_prefix};
+use crate::process::registry::Registry;
+
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(500); // 读到文件末尾后，等待新内容的间隔
+
+#[derive(Serialize)]
+pub struct LogFileOut {
+    name: String,
+    size: u64,
+    modified: Option<String>,
+}
+
+// 只允许访问 pipe_logger 写出的日志文件：{stdout|stderr}.{时间}.log[.gz]
+fn is_log_file_name(name: &str) -> bool {
+    let Some((kind, rest)) = name.split_once('.') else {
+        return false;
+    };
+    if kind != "stdout" && kind != "stderr" {
+        return false;
+    }
+    let date = rest
+        .strip_suffix(".log.gz")
+        .or_else(|| rest.strip_suffix(".log"))
+        .unwrap_or("");
+    !date.is_empty() && date.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[derive(Deserialize)]
+pub struct LogFileQuery {
+    #[serde(default)]
+    raw: bool, // 为 true 时 .gz 文件原样返回，不解压
+}
+
+// 进程的日志目录；进程不存在时返回 None，未配置 output_dir 时返回空路径
+fn output_dir(reg: &Registry, name: &str) -> Option<PathBuf> {
+    reg.find(name).map(|pe| PathBuf::from(pe.cmd.output_dir))
+}
+
+// 列出进程 output_dir 中的日志文件，按文件名倒序（最新的在前）
+pub async fn list_logfiles(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+) -> Response {
+    let Some(dir) = output_dir(&reg, &name) else {
+        return (StatusCode::NOT_FOUND, "process not found").into_response();
+    };
+
+    let mut files = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !is_log_file_name(&file_name) {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if !meta.is_file() {
+                continue;
+            }
+            files.push(LogFileOut {
+                name: file_name,
+                size: meta.len(),
+                modified: meta
+                    .modified()
+                    .ok()
+                    .map(|t| DateTime::<Local>::from(t).format("%Y-%m-%d %H:%M:%S").to_string()),
+            });
+        }
+    }
+    files.sort_by(|a, b| b.name.cmp(&a.name));
+    Json(files).into_response()
+}
+
+// 检查文件名并确认文件真实位于 output_dir 中（防止通过符号链接访问目录外的文件）
+fn resolve_logfile(dir: &Path, filename: &str) -> Result<PathBuf, (StatusCode, &'static str)> {
+    if !is_log_file_name(filename) {
+        return Err((StatusCode::BAD_REQUEST, "invalid log file name"));
+    }
+    if dir.as_os_str().is_empty() {
+        return Err((StatusCode::NOT_FOUND, "log file not found"));
+    }
+    let (Ok(dir), Ok(path)) = (dir.canonicalize(), dir.join(filename).canonicalize()) else {
+        return Err((StatusCode::NOT_FOUND, "log file not found"));
+    };
+    if path.parent() != Some(dir.as_path()) || !path.is_file() {
+        return Err((StatusCode::NOT_FOUND, "log file not found"));
+    }
+    Ok(path)
+}
+
+// 以流的方式返回一个日志文件的内容，.gz 文件默认边读边解压成文本
+pub async fn get_logfile(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path((name, filename)): extract::Path<(String, String)>,
+    extract::Query(q): extract::Query<LogFileQuery>,
+) -> Response {
+    let Some(dir) = output_dir(&reg, &name) else {
+        return (StatusCode::NOT_FOUND, "process not found").into_response();
+    };
+    let path = match resolve_logfile(&dir, &filename) {
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::warn!("failed to open {}: {:?}", path.display(), e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "failed to open log file").into_response();
+        }
+    };
+
+    if !filename.ends_with(".gz") {
+        return (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response();
+    }
+    if q.raw {
+        return (
+            [(header::CONTENT_TYPE, "application/gzip")],
+            Body::from_stream(ReaderStream::new(file)),
+        )
+            .into_response();
+    }
+
+    // flate2 只有同步接口，在 blocking 线程中解压并写入管道，响应从管道另一端读取
+    let (reader, writer) = tokio::io::duplex(64 * 1024);
+    let mut writer = SyncIoBridge::new(writer);
+    let file = file.into_std().await;
+    tokio::task::spawn_blocking(move || {
+        let mut decoder = flate2::read::MultiGzDecoder::new(std::io::BufReader::new(file));
+        if let Err(e) = std::io::copy(&mut decoder, &mut writer) {
+            // 客户端断开或文件损坏
+            tracing::warn!("failed to decompress {}: {:?}", path.display(), e);
+        }
+    });
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+        .into_response()
+}
+
+#[derive(Deserialize)]
+pub struct TailQuery {
+    #[serde(default = "default_tail_kind")]
+    kind: String, // stdout 或 stderr，默认 stdout
+}
+
+fn default_tail_kind() -> String {
+    "stdout".to_string()
+}
+
+// 跟踪当前小时的日志文件，读到末尾后等待新内容，小时变化时切换到新文件
+struct LogTail {
+    dir: PathBuf,
+    kind: String,
+    tz: LogTimezone,
+    name: String,                  // 正在跟踪的文件名
+    file: Option<tokio::fs::File>, // 文件尚未创建时为 None
+    partial: Vec<u8>,              // 还没有换行符的半行
+    lines: VecDeque<String>,       // 等待发送的完整行
+}
+
+impl LogTail {
+    // 请求时已存在的文件从末尾开始（tail -f），之后出现的文件（包括切换后的新文件）从头读取
+    async fn new(dir: PathBuf, kind: String, tz: LogTimezone) -> Self {
+        let name = current_log_file_name(&kind, tz);
+        let mut tail = LogTail {
+            dir,
+            kind,
+            tz,
+            name,
+            file: None,
+            partial: Vec::new(),
+            lines: VecDeque::new(),
+        };
+        tail.open().await;
+        if let Some(f) = tail.file.as_mut()
+            && let Err(e) = f.seek(std::io::SeekFrom::End(0)).await
+        {
+            tracing::warn!("seek {} failed: {:?}", tail.name, e);
+        }
+        tail
+    }
+
+    async fn open(&mut self) {
+        let Ok(path) = resolve_logfile(&self.dir, &self.name) else {
+            return;
+        };
+        match tokio::fs::File::open(&path).await {
+            Ok(f) => self.file = Some(f),
+            Err(e) => tracing::warn!("failed to open {}: {:?}", path.display(), e),
+        }
+    }
+
+    fn push(&mut self, data: &[u8]) {
+        for segment in data.split_inclusive(|b| *b == b'\n') {
+            self.partial.extend_from_slice(segment);
+            if segment.ends_with(b"\n") {
+                let line = std::mem::take(&mut self.partial);
+                self.push_line(&line);
+            }
+        }
+    }
+
+    fn push_line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        self.lines.push_back(line.trim_end_matches(['\n', '\r']).to_string());
+    }
+
+    async fn next_line(&mut self) -> String {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return line;
+            }
+
+            if self.file.is_none() {
+                self.open().await;
+            }
+            if let Some(f) = self.file.as_mut() {
+                match f.read(&mut buf).await {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        self.push(&buf[..n]);
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!("read {} failed: {:?}", self.name, e);
+                        self.file = None;
+                    }
+                }
+            }
+
+            // 旧文件已读完，小时变化后切换到新文件
+            let name = current_log_file_name(&self.kind, self.tz);
+            if name != self.name {
+                tracing::debug!("tail {} -> {}", self.name, name);
+                if !self.partial.is_empty() {
+                    let partial = std::mem::take(&mut self.partial);
+                    self.push_line(&partial);
+                }
+                self.name = name;
+                self.file = None;
+                continue;
+            }
+            tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+        }
+    }
+}
+
+// 以 SSE 的方式持续推送进程当前日志文件新增的行（tail -f），跨小时自动切换到新文件
+pub async fn tail_logfile(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(q): extract::Query<TailQuery>,
+) -> Response {
+    let Some(pe) = reg.find(&name) else {
+        return (StatusCode::NOT_FOUND, "process not found").into_response();
+    };
+    if q.kind != "stdout" && q.kind != "stderr" {
+        return (StatusCode::BAD_REQUEST, "kind must be stdout or stderr").into_response();
+    }
+    if pe.cmd.output_dir.is_empty() {
+        return (StatusCode::NOT_FOUND, "output_dir is not configured").into_response();
+    }
+
+    let tail = LogTail::new(PathBuf::from(pe.cmd.output_dir), q.kind, pe.cmd.log_timezone).await;
+    let stream = futures_util::stream::unfold(tail, |mut tail| async move {
+        let line = tail.next_line().await;
+        Some((Ok::<_, Infallible>(Event::default().data(line)), tail))
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct MergedQuery {
+    hour: Option<String>, // 日志文件的小时，如 2024010112，默认当前小时
+}
+
+// 按行读取某个小时的日志文件（已压缩的 .gz 文件边读边解压），没有序号前缀的行沿用上一行的序号
+struct SeqLines {
+    reader: Option<Box<dyn BufRead + Send>>, // 文件不存在或已读完时为 None
+    last_seq: u64,
+}
+
+impl SeqLines {
+    fn open(dir: &Path, kind: &str, hour: &str) -> Self {
+        let name = format!("{kind}.{hour}.log");
+        let reader: Option<Box<dyn BufRead + Send>> = if let Ok(path) = resolve_logfile(dir, &name) {
+            std::fs::File::open(path)
+                .ok()
+                .map(|f| Box::new(std::io::BufReader::new(f)) as _)
+        } else if let Ok(path) = resolve_logfile(dir, &format!("{name}.gz")) {
+            std::fs::File::open(path).ok().map(|f| {
+                let decoder = flate2::read::MultiGzDecoder::new(std::io::BufReader::new(f));
+                Box::new(std::io::BufReader::new(decoder)) as _
+            })
+        } else {
+            None
+        };
+        SeqLines { reader, last_seq: 0 }
+    }
+
+    fn next(&mut self) -> Option<(u64, Vec<u8>)> {
+        let reader = self.reader.as_mut()?;
+        let mut line = Vec::new();
+        if !matches!(reader.read_until(b'\n', &mut line), Ok(n) if n > 0) {
+            self.reader = None;
+            return None;
+        }
+        if let Some((seq, rest)) = parse_seq_prefix(&line) {
+            self.last_seq = seq;
+            line = rest.to_vec();
+        }
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+        Some((self.last_seq, line))
+    }
+}
+
+// 按序号合并同一小时的 stdout、stderr 日志文件，每行前加上 [stdout] 或 [stderr]
+// 需要进程配置 log_seq: true，否则文件中没有序号，只能先输出 stdout 再输出 stderr
+pub async fn merged_logfile(
+    Extension(reg): Extension<Arc<Registry>>,
+    extract::Path(name): extract::Path<String>,
+    extract::Query(q): extract::Query<MergedQuery>,
+) -> Response {
+    let Some(pe) = reg.find(&name) else {
+        return (StatusCode::NOT_FOUND, "process not found").into_response();
+    };
+    if pe.cmd.output_dir.is_empty() {
+        return (StatusCode::NOT_FOUND, "output_dir is not configured").into_response();
+    }
+    let hour = q.hour.unwrap_or_else(|| current_hour(pe.cmd.log_timezone));
+    if hour.is_empty() || !hour.bytes().all(|b| b.is_ascii_digit()) {
+        return (StatusCode::BAD_REQUEST, "hour must be like 2024010112").into_response();
+    }
+
+    let dir = PathBuf::from(pe.cmd.output_dir);
+    let mut stdout = SeqLines::open(&dir, "stdout", &hour);
+    let mut stderr = SeqLines::open(&dir, "stderr", &hour);
+    if stdout.reader.is_none() && stderr.reader.is_none() {
+        return (StatusCode::NOT_FOUND, "log file not found").into_response();
+    }
+
+    // 两个文件各自按序号递增，依次取序号较小的一行；在 blocking 线程中读取并写入管道
+    let (reader, writer) = tokio::io::duplex(64 * 1024);
+    let mut writer = SyncIoBridge::new(writer);
+    tokio::task::spawn_blocking(move || {
+        let mut out_line = stdout.next();
+        let mut err_line = stderr.next();
+        loop {
+            let (kind, line) = match (&out_line, &err_line) {
+                (None, None) => break,
+                (Some(o), Some(e)) if e.0 < o.0 => ("stderr", err_line.take()),
+                (Some(_), _) => ("stdout", out_line.take()),
+                (None, Some(_)) => ("stderr", err_line.take()),
+            };
+            let Some((_, line)) = line else {
+                break;
+            };
+            if let Err(e) = write!(writer, "[{kind}] ").and_then(|_| writer.write_all(&line)) {
+                tracing::debug!("write merged log failed: {:?}", e);
+                break;
+            }
+            if kind == "stdout" {
+                out_line = stdout.next();
+            } else {
+                err_line = stderr.next();
+            }
+        }
+    });
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        Body::from_stream(ReaderStream::new(reader)),
+    )
+        .into_response()
+}
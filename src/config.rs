@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
 use std::path::PathBuf;
 use std::{env, sync::Arc};
@@ -6,6 +7,7 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use tokio::time::Duration;
 
+use crate::error::ProcdError;
 use crate::process;
 use crate::process::registry::Registry;
 
@@ -13,8 +15,12 @@ use crate::process::registry::Registry;
 pub struct Config {
     pub http: HttpConfig, // web server 配置
 
+    #[serde(default)]
     pub process: Vec<ProcessConfig>, // 子进程配置列表
 
+    #[serde(default)]
+    pub process_dir: Option<String>, // 每个文件一个进程配置的目录，如 processes.d，相对路径基于主配置文件所在目录
+
     #[serde(default)]
     pub sandbox: Vec<SandboxConfig>, // 沙盒环境配置列表
 
@@ -24,6 +30,15 @@ pub struct Config {
     #[serde(default)]
     pub log_dir: String, // 日志目录
 
+    #[serde(default)]
+    pub log_format: LogFormat, // procd 自身日志（控制台和 log_dir 中的 procd.log）的格式，默认 full
+
+    #[serde(default = "default_true")]
+    pub log_timestamp: bool, // procd 自身日志是否输出时间，默认 true；由 systemd/docker 等收集时可关闭
+
+    #[serde(default = "default_true")]
+    pub log_target: bool, // procd 自身日志是否输出 target（模块路径），默认 true
+
     #[serde(default)]
     pub auth: AuthConfig, // web 页面认证信息
 
@@ -35,11 +50,89 @@ pub struct Config {
 
     #[serde(default = "default_true")]
     pub enable_sandbox: bool, // 是否进入沙盒以安全运行,若为false，则所有子进程都为 false
+
+    #[serde(default)]
+    pub total_memory_limit: Option<u64>, // 所有子进程（含其子进程）的内存总和上限，单位 MB
+
+    #[serde(default)]
+    pub total_memory_action: MemoryAction, // 超过 total_memory_limit 时的动作，默认只输出警告
+
+    #[serde(default)]
+    pub on_shutdown: Option<Vec<String>>, // procd 收到 SIGTERM/SIGINT、停止全部进程后运行的命令
+
+    #[serde(default = "default_on_shutdown_timeout", with = "humantime_serde")]
+    pub on_shutdown_timeout: Duration, // on_shutdown 最长运行时长，超时后杀死，默认 30s
+
+    #[serde(default)]
+    pub shutdown_order: ShutdownOrder, // procd 退出时停止进程的顺序，默认 parallel
+
+    #[serde(default)]
+    pub log_buffer_bytes: Option<usize>, // 读取子进程输出的缓冲区大小，进程未配置 log_buffer_bytes 时使用，默认 4096
+
+    #[serde(default)]
+    pub total_log_buffer_bytes: Option<usize>, // 所有进程内存中保留的最近 stderr 行的总字节数上限，超过时先裁剪 log_buffer_priority 低的进程，默认不限制
+
+    #[serde(default)]
+    pub allowed_commands: Vec<String>, // 允许启动的命令（绝对路径或 PATH 中的命令名），不为空时其他命令不会被启动，按 canonicalize 后的路径比较
+
+    #[serde(default = "default_max_processes")]
+    pub max_processes: usize, // 管理的进程数量上限（含 process_dir 中的），超过时拒绝加载配置，默认 1000
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryAction {
+    #[default]
+    Warn, // 只输出警告日志
+    KillLargest, // 杀死占用内存最多的进程，之后不会自动重启
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpConfig {
-    pub addr: String,
+    pub addr: ListenAddr, // 监听地址，可以是单个地址或地址列表
+
+    #[serde(default)]
+    pub web_root: String, // 静态资源目录，可选，为空时使用内置的 index.html
+
+    #[serde(default)]
+    pub cors_origins: Vec<String>, // 允许跨域访问的来源，可选，默认为空，不输出 CORS 头
+
+    #[serde(default)]
+    pub required_header: Option<RequiredHeader>, // 所有请求必须携带的请求头，可选，一般由前置网关注入
+
+    #[serde(default, with = "humantime_serde::option")]
+    pub request_timeout: Option<Duration>, // 处理请求到返回响应头的最长时间，超时返回 408，可选，不影响 SSE 等流式接口
+
+    #[serde(default, with = "humantime_serde::option")]
+    pub read_timeout: Option<Duration>, // 读取请求体时两次收到数据的最长间隔，可选
+
+    #[serde(default, with = "humantime_serde::option")]
+    pub write_timeout: Option<Duration>, // 发送响应体时两次写出数据的最长间隔，客户端读取过慢时断开，可选
+
+    #[serde(default)]
+    pub read_only: bool, // 只读模式，启动、停止、重启、reload 等修改状态的接口返回 405，查询接口不受影响，与认证无关
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RequiredHeader {
+    pub name: String,  // 请求头名称，如 X-Procd-Key
+    pub value: String, // 请求头的值
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ListenAddr {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ListenAddr {
+    pub fn list(&self) -> Vec<String> {
+        match self {
+            ListenAddr::One(addr) => vec![addr.clone()],
+            ListenAddr::Many(addrs) => addrs.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -47,11 +140,42 @@ pub struct HttpConfig {
 pub struct AuthConfig {
     pub username: String,
     pub password: String,
+    pub users: Vec<AuthUser>, // 只能查看和管理部分进程的账号，可选
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthUser {
+    pub username: String,
+    pub password: String,
+    pub processes: Vec<String>, // 允许访问的进程名称前缀
+    pub projects: Vec<String>,  // 允许访问的项目（进程的 project）
+}
+
+impl AuthUser {
+    // 名称匹配任一前缀，或属于任一项目的进程可以访问
+    pub fn allows(&self, name: &str, project: Option<&str>) -> bool {
+        self.processes.iter().any(|p| name.starts_with(p.as_str()))
+            || project.is_some_and(|project| self.projects.iter().any(|p| p == project))
+    }
 }
 
 impl AuthConfig {
+    // 是否需要认证：配置了主账号或受限账号
+    pub fn enabled(&self) -> bool {
+        !self.username.is_empty() || !self.users.is_empty()
+    }
+
+    // 主账号，可以访问全部进程和接口
     pub fn check(&self, name: &str, psw: &str) -> bool {
-        return self.username == name && self.password == psw;
+        !self.username.is_empty() && self.username == name && self.password == psw
+    }
+
+    // 受限账号，账号或密码不匹配时返回 None
+    pub fn find_user(&self, name: &str, psw: &str) -> Option<&AuthUser> {
+        self.users
+            .iter()
+            .find(|u| !u.username.is_empty() && u.username == name && u.password == psw)
     }
 }
 
@@ -77,22 +201,43 @@ impl SandboxConfig {
 
         let mut cmd = self.cmd.clone();
         cmd[0] = first;
-        return cmd;
+        cmd
     }
 }
 
-#[derive(Serialize, Debug, Deserialize, Clone)]
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
 pub struct ProcessConfig {
     pub name: String,
 
+    #[serde(default)]
+    pub project: Option<String>, // 所属项目，管理页面按项目分组展示
+
+    #[serde(default)]
+    pub description: String, // 进程的说明，如 "nightly billing export"，只用于展示
+
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>, // 自定义标签，如 {env: prod, team: billing}，名称需符合 Prometheus 的标签规则
+
     pub cmd: String, // 程序命令，必填
 
+    #[serde(default)]
+    pub cmd_fallbacks: Vec<String>, // cmd 找不到时依次尝试的备选命令，如 ["python"]
+
     #[serde(default)]
     pub args: Vec<String>, // 命令参数，可选, 可能是 ./app （从 home 目录查找） 或者 app （从Path查找）或者 /opt/app （绝对地址）
 
+    #[serde(default)]
+    pub arg0: Option<String>, // 覆盖子进程的 argv[0]，仅 Unix 且未使用沙盒时生效
+
     #[serde(default)]
     pub envs: Vec<String>, // 额外的环境变量值
 
+    #[serde(default)]
+    pub env_clear: bool, // 不继承 procd 的环境变量，只使用 env_passthrough 和 envs，默认 false
+
+    #[serde(default)]
+    pub env_passthrough: Vec<String>, // env_clear 时仍从 procd 继承的环境变量名，如 PATH、LANG
+
     #[serde(default)]
     pub home: String, // 进程根目录
 
@@ -102,41 +247,397 @@ pub struct ProcessConfig {
     #[serde(default)]
     pub output_dir: String, // 单独的日志输出目录
 
+    #[serde(default)]
+    pub stdout_target: Option<OutputTarget>, // stdout 的去向：file、log、both、null，为空时由 redirect_output 决定
+
+    #[serde(default)]
+    pub stderr_target: Option<OutputTarget>, // stderr 的去向，同 stdout_target
+
+    #[serde(default = "default_log_level")]
+    pub log_level: String, // stdout 和 stderr 写入 procd 日志时的级别，默认 debug，可通过 API 在运行时修改
+
+    #[serde(default)]
+    pub log_timezone: LogTimezone, // 日志文件名使用的时区，默认 local
+
+    #[serde(default)]
+    pub encoding: Option<String>, // 子进程输出的编码，如 gbk、latin1，写入日志前转为 UTF-8，默认按 UTF-8 处理
+
+    #[serde(default)]
+    pub log_json_passthrough: bool, // 按行解析 JSON 日志，以其中的级别写入 procd 日志，不是 JSON 的行按原样输出
+
+    #[serde(default = "default_log_json_level_field")]
+    pub log_json_level_field: String, // JSON 日志中级别的字段名，默认 level
+
+    #[serde(default)]
+    pub log_json_message_field: Option<String>, // JSON 日志中消息的字段名，默认依次尝试 msg、message
+
+    #[serde(default = "default_max_log_line")]
+    pub max_log_line: Option<usize>, // 单行输出的最大字节数，超出部分截断并追加标记，默认 64KB，设为 null 或 0 时不限制
+
+    #[serde(skip)]
+    pub allowed_commands: Vec<String>, // 全局的 allowed_commands，由 check_and_init 设置，不能在进程中配置
+
+    #[serde(default)]
+    pub log_buffer_bytes: Option<usize>, // 每次读取子进程输出的最大字节数，只影响读取的次数，不影响按行处理，默认使用全局配置或 4096
+
+    #[serde(default)]
+    pub log_buffer_priority: u8, // 最近 stderr 行的保留优先级，超过 total_log_buffer_bytes 时先裁剪优先级低的进程，默认 0
+
+    #[serde(default)]
+    pub log_mode: LogMode, // 进程启动时日志文件的打开方式：append 追加，truncate 清空，默认 append
+
+    #[serde(default)]
+    pub log_seq: bool, // 日志文件的每行前加上 "#序号 "，序号在 stdout、stderr 之间全局递增，用于合并两个文件时还原先后顺序
+
+    #[serde(default, deserialize_with = "deserialize_octal_mode")]
+    pub log_mode_perms: Option<u32>, // 新建日志文件的权限（八进制，如 "0600"），新建的日志目录使用相同的权限并加上执行位，仅 Unix，默认由 umask 决定
+
     #[serde(default, with = "humantime_serde::option")]
     pub max_run: Option<Duration>, // 最大运行时长，秒数，配置文件配置值 "10s"、"1h30m"
 
+    #[serde(default)]
+    pub max_run_action: MaxRunAction, // 达到 max_run 后重启还是停止，默认 restart
+
+    #[serde(default)]
+    pub max_run_signal: Option<String>, // 达到 max_run 时先发送的信号（如 SIGTERM、SIGINT），等待 term_timeout（默认 10s）后仍未退出再 SIGKILL，仅 Unix；为空时与普通停止相同
+
     #[serde(default, with = "humantime_serde::option")]
     pub next: Option<Duration>, // 下一次运行距离上次退出的时间间隔
 
+    #[serde(default, with = "humantime_serde::option")]
+    pub term_timeout: Option<Duration>, // 停止时先发送 SIGTERM，等待该时长仍未退出再 SIGKILL，为空时直接 SIGKILL
+
     #[serde(default)]
     pub memory_limit: Option<u32>, // 内存限制,单位 MB
 
     #[serde(default)]
-    pub web_address: String, // 通过管理页面访问的地址，支持变量 ${HOST}
+    pub cpu_affinity: Vec<usize>, // 绑定到这些 CPU 核心（从 0 开始），仅 Linux 生效，为空时不限制
+
+    #[serde(default)]
+    pub netns: Option<String>, // 在该命名网络命名空间（/var/run/netns/<name>）中运行，仅 Linux 生效，需要 root
+
+    #[serde(default)]
+    pub chroot: Option<String>, // 以该目录为根目录运行，cmd 和 home 为 chroot 内的路径，仅 Unix 生效，需要 root
+
+    #[serde(default)]
+    pub socket: Option<String>, // socket activation：procd 监听该地址（host:port 或 unix:/path），以 fd 3 传给子进程，重启期间 socket 保持打开，仅 Unix
+
+    #[serde(default)]
+    pub seccomp_profile: Option<String>, // seccomp 系统调用过滤规则文件，仅 Linux 且编译时启用 seccomp feature 时可用
+
+    #[serde(default)]
+    pub restart_on_memory: Option<u32>, // 进程（含子进程）内存超过该值（MB）持续 restart_on_memory_for 后重启
+
+    #[serde(default = "default_restart_on_memory_for", with = "humantime_serde")]
+    pub restart_on_memory_for: Duration, // 内存持续超过 restart_on_memory 多久后重启，避免短暂峰值导致重启，默认 30s
+
+    #[serde(default)]
+    pub web_address: String, // 通过管理页面访问的地址，支持变量 {HOST}、{PORT}、{PID}、{NAME}
+
+    #[serde(default)]
+    pub port: Option<u16>, // 进程监听的端口，用于 web_address 中的 {PORT}，为空时使用检测到的监听端口
 
     #[serde(default = "default_true")]
     pub enable: bool, // 该配置是否启用，默认为 true
 
+    #[serde(default = "default_true")]
+    pub auto_start: bool, // procd 启动时是否自动启动，为 false 时只注册，需通过 API 手动启动，默认为 true
+
+    #[serde(default)]
+    pub start_priority: i32, // 启动优先级，procd 启动时值大的进程先启动，相同时按配置顺序，默认 0
+
+    #[serde(default)]
+    pub only_on_hosts: Vec<String>, // 仅在这些主机名上启用，为空时不限制
+
+    #[serde(default)]
+    pub only_if_env: HashMap<String, String>, // 仅当 procd 的环境变量都匹配这些值时启用，为空时不限制
+
     #[serde(default)]
     pub use_sandbox: String, // 使用沙盒的名称
 
     #[serde(default)]
     pub sandbox: Vec<String>, // 沙盒的命令
+
+    #[serde(default)]
+    pub restart: RestartPolicy, // 进程退出后是否重启，默认 always
+
+    #[serde(default)]
+    pub max_restarts: Option<u32>, // restart_window 内最多自动重启的次数，超过后停止重启并标记为 Error
+
+    #[serde(default = "default_restart_window", with = "humantime_serde")]
+    pub restart_window: Duration, // 统计自动重启次数的时间窗口，默认 60s
+
+    #[serde(default = "default_min_uptime", with = "humantime_serde")]
+    pub min_uptime: Duration, // 持续运行超过该时长视为启动成功，restarts_since_success 清零，默认 10s
+
+    #[serde(default)]
+    pub restart_on_signals: Vec<String>, // 仅在被这些信号杀死时重启，如 ["SIGSEGV", "SIGKILL"]，为空时总是重启
+
+    #[serde(default)]
+    pub health_check: Option<HealthCheckConfig>, // 健康检查，可选
+
+    #[serde(default)]
+    pub ready_file: Option<String>, // 进程创建该文件或 unix socket 后才视为就绪，之前状态为 Starting，相对路径基于 home
+
+    #[serde(default = "default_ready_timeout", with = "humantime_serde")]
+    pub ready_timeout: Duration, // 等待 ready_file 的最长时间，超时后重启进程，默认 30s
+
+    #[serde(default, with = "humantime_serde::option")]
+    pub start_timeout: Option<Duration>, // 处于 Starting 状态的最长时间，超时后杀死进程并标记为 Error，再按 restart 策略处理
+
+    #[serde(default)]
+    pub pre_start: Option<Vec<String>>, // 每次启动前运行的命令，退出码非 0 时不启动进程
+
+    #[serde(default)]
+    pub restart_cmd: Option<Vec<String>>, // 重启时运行的命令（如 nginx -s reload），成功则进程继续运行，失败时再杀死并重新启动
+
+    #[serde(default)]
+    pub on_exit: Option<Vec<String>>, // 进程每次退出后运行的命令，通过环境变量 PROCD_NAME、PROCD_EXIT_CODE 获取退出信息
+}
+
+// procd 退出时停止进程的顺序
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownOrder {
+    #[default]
+    Parallel, // 同时停止全部进程
+    Priority, // 与启动顺序相反：start_priority 小的先停止，同一优先级的进程同时停止，全部停止后再停止下一组
+}
+
+// procd 自身日志的格式，对应 tracing_subscriber::fmt 的几种格式；/api/logs 的内容不受影响
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Full, // 默认的单行格式
+    Compact, // 更紧凑的单行格式
+    Pretty,  // 多行格式，适合本地调试
+    Json,    // 每行一个 JSON 对象，适合日志收集系统
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RestartPolicy {
+    #[default]
+    Always, // 退出后总是重启
+    Never, // 运行一次，退出后不再重启
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MaxRunAction {
+    #[default]
+    Restart, // 定期重启，如缓解内存泄漏
+    Stop, // 最多运行 max_run 时长，之后不再启动
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputTarget {
+    File, // 只写入 output_dir 中的日志文件
+    Log,  // 只写入 procd 日志
+    Both, // 同时写入日志文件和 procd 日志
+    Null, // 丢弃
+}
+
+impl OutputTarget {
+    pub fn to_file(self) -> bool {
+        matches!(self, OutputTarget::File | OutputTarget::Both)
+    }
+
+    pub fn to_log(self) -> bool {
+        matches!(self, OutputTarget::Log | OutputTarget::Both)
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogMode {
+    #[default]
+    Append, // 追加到已有的日志文件
+    Truncate, // 每次启动时清空当前小时的日志文件，只保留最近一次运行的输出；按小时切分时不清空
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTimezone {
+    #[default]
+    Local, // 服务器本地时区
+    Utc,
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, PartialEq)]
+pub struct HealthCheckConfig {
+    #[serde(default)]
+    pub tcp: String, // 检查的 TCP 地址，能建立连接即为健康，如 "127.0.0.1:8090"、"[::1]:8090"、"localhost:8090"
+
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>, // 检查命令，在进程的 home 和环境变量下运行，退出码为 0 即为健康；与 tcp 同时配置时都通过才健康
+
+    #[serde(default = "default_health_interval", with = "humantime_serde")]
+    pub interval: Duration, // 检查间隔，默认 10s
+
+    #[serde(default = "default_health_timeout", with = "humantime_serde")]
+    pub timeout: Duration, // 单次检查的超时时间，默认 3s
+
+    #[serde(default = "default_health_failures")]
+    pub failures: u32, // 连续失败多少次后重启进程，默认 3
+}
+
+// parse_signal 解析信号名称，支持 "SIGTERM"、"TERM"（不区分大小写）和信号编号
+#[cfg(unix)]
+pub fn parse_signal(name: &str) -> Option<nix::sys::signal::Signal> {
+    use nix::sys::signal::Signal;
+    let name = name.trim().to_uppercase();
+    if let Ok(n) = name.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    match name.starts_with("SIG") {
+        true => name.parse().ok(),
+        false => format!("SIG{}", name).parse().ok(),
+    }
+}
+
+fn default_on_shutdown_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_restart_window() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_min_uptime() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_restart_on_memory_for() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_ready_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_health_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_health_timeout() -> Duration {
+    Duration::from_secs(3)
+}
+
+fn default_health_failures() -> u32 {
+    3
+}
+
+const PROCESS_FILE_EXTENSIONS: [&str; 4] = ["yml", "yaml", "toml", "json"]; // process_dir 中加载的配置文件类型
+
+// process_files 列出 process_dir 中的进程配置文件，按文件名排序；跳过隐藏文件和其他扩展名的文件
+pub fn process_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.'));
+        let matched = path
+            .extension()
+            .is_some_and(|ext| PROCESS_FILE_EXTENSIONS.iter().any(|e| ext.eq_ignore_ascii_case(e)));
+        if path.is_file() && !hidden && matched {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+const SECRET_FILE_PREFIX: &str = "@file:"; // 环境变量的值从文件读取
+
+// resolve_envs 解析 "k=v" 形式的环境变量，值为 "@file:/path" 时从文件读取（如 Docker/K8s 挂载的 secret），去掉末尾的换行
+pub fn resolve_envs(list: &[String]) -> anyhow::Result<Vec<(String, String)>> {
+    let mut envs = Vec::with_capacity(list.len());
+    for env in list {
+        let Some((key, value)) = env.split_once("=") else {
+            continue;
+        };
+        let value = match value.strip_prefix(SECRET_FILE_PREFIX) {
+            Some(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read secret file {} for env {}", path, key))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string(),
+            None => value.to_string(),
+        };
+        envs.push((key.to_string(), value));
+    }
+    Ok(envs)
+}
+
+// secret_env_keys 返回值通过 @file: 从文件读取的变量名，这些值展示时总是隐藏，与变量名无关
+// 同名变量以最后一次出现为准，与 resolve_envs 设置到 Command 上的结果一致
+pub fn secret_env_keys(list: &[String]) -> HashSet<String> {
+    let mut keys = HashSet::new();
+    for (key, value) in list.iter().filter_map(|env| env.split_once("=")) {
+        if value.starts_with(SECRET_FILE_PREFIX) {
+            keys.insert(key.to_string());
+        } else {
+            keys.remove(key);
+        }
+    }
+    keys
+}
+
+fn default_log_json_level_field() -> String {
+    "level".to_string()
+}
+
+fn default_max_log_line() -> Option<usize> {
+    Some(64 * 1024)
+}
+
+fn default_log_level() -> String {
+    "debug".to_string()
+}
+
+fn default_max_processes() -> usize {
+    1000
 }
 
 fn default_true() -> bool {
     true
 }
 
+// 环境变量名中包含这些关键字时，对外展示时隐藏其值
+const SECRET_ENV_PATTERNS: [&str; 6] = ["PASSWORD", "PASSWD", "SECRET", "TOKEN", "KEY", "CREDENTIAL"];
+
+// redact_env 对 "K=V" 格式的环境变量脱敏，用于 API 输出
+pub fn redact_env(env: &str) -> String {
+    if let Some((key, _)) = env.split_once('=') {
+        let upper = key.to_uppercase();
+        if SECRET_ENV_PATTERNS.iter().any(|p| upper.contains(p)) {
+            return format!("{}=******", key);
+        }
+    }
+    env.to_string()
+}
+
 impl Config {
     fn check_and_init(&mut self) {
         let sbox = self.sandbox.clone();
+        let hostname = sysinfo::System::host_name().unwrap_or_default();
         for pc in self.process.iter_mut() {
+            if pc.enable && !pc.match_host_and_env(&hostname) {
+                tracing::info!("{}: only_on_hosts or only_if_env not matched, disabled", pc.name);
+                pc.enable = false;
+            }
+
             // 合并全局的环境变量
             let mut merged = self.envs.clone();
             merged.extend(pc.envs.clone());
             pc.envs = merged;
 
+            if pc.log_buffer_bytes.is_none() {
+                pc.log_buffer_bytes = self.log_buffer_bytes;
+            }
+            pc.allowed_commands = self.allowed_commands.clone();
+
             if !self.log_dir.is_empty() && pc.output_dir.is_empty() {
                 let mut path = std::path::PathBuf::from(&self.log_dir);
                 path.push(&pc.name);
@@ -156,18 +657,124 @@ impl Config {
         }
     }
 
-    pub fn from_file(path: &str) -> anyhow::Result<Config> {
+    // validate 检查反序列化之外的语义约束，返回全部错误
+    // 需要在 check_and_init 和 set_current_dir 之后调用，这样沙盒、output_dir 已确定，相对路径也能正确解析
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let cpu_count = cpu_count();
+        let mut names = HashSet::new();
+
+        if self.total_memory_limit == Some(0) {
+            errors.push(ConfigError::new("total_memory_limit", "must be greater than 0"));
+        }
+        if self.total_log_buffer_bytes == Some(0) {
+            errors.push(ConfigError::new("total_log_buffer_bytes", "must be greater than 0"));
+        }
+        for (i, user) in self.auth.users.iter().enumerate() {
+            let field = |name: &str| format!("auth.users[{}].{}", i, name);
+            if user.username.is_empty() {
+                errors.push(ConfigError::new(field("username"), "must not be empty"));
+            } else if user.username == self.auth.username {
+                errors.push(ConfigError::new(field("username"), "same as auth.username"));
+            }
+            if user.processes.is_empty() && user.projects.is_empty() {
+                errors.push(ConfigError::new(
+                    field("processes"),
+                    "processes or projects must not be empty",
+                ));
+            }
+        }
+        if let Some(size) = self.log_buffer_bytes
+            && !LOG_BUFFER_RANGE.contains(&size)
+        {
+            errors.push(ConfigError::new("log_buffer_bytes", log_buffer_range_message()));
+        }
+        if let Some(program) = self.on_shutdown.as_ref().and_then(|h| h.first())
+            && let Err(e) = check_command_allowed(&self.allowed_commands, program, &self.home)
+        {
+            errors.push(ConfigError::new("on_shutdown", e.to_string()));
+        }
+        for cmd in &self.allowed_commands {
+            // 相对路径依赖当前目录，含路径分隔符时必须是绝对路径
+            if cmd.trim().is_empty() || (cmd.contains(std::path::MAIN_SEPARATOR) && !Path::new(cmd).is_absolute()) {
+                errors.push(ConfigError::new(
+                    "allowed_commands",
+                    format!("{:?} must be an absolute path or a command name", cmd),
+                ));
+            }
+        }
+
+        for pc in &self.process {
+            let field = |name: &str| format!("process[{}].{}", pc.name, name);
+            if pc.name.is_empty() {
+                errors.push(ConfigError::new(field("name"), "must not be empty"));
+            }
+            if !names.insert(pc.name.as_str()) {
+                errors.push(ConfigError::new(field("name"), "duplicated process name"));
+            }
+            // 未启用的进程可能是为其他主机准备的，不检查
+            if !pc.enable {
+                continue;
+            }
+            for (name, message) in pc.validate(cpu_count) {
+                errors.push(ConfigError::new(field(name), message));
+            }
+        }
+        errors
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, ProcdError> {
+        let config_error = |path: &str| {
+            let path = path.to_string();
+            move |source| ProcdError::Config { path, source }
+        };
         let settings = config::Config::builder()
             .add_source(config::File::with_name(path)) // 1. 加载文件
-            .build()?;
+            .build()
+            .map_err(config_error(path))?;
 
         // 3. 转换成 struct
-        let mut cfg: Config = settings.try_deserialize()?;
+        let mut cfg: Config = settings.try_deserialize().map_err(config_error(path))?;
+
+        // 4. 合并 process_dir 中的进程配置
+        if let Some(dir) = cfg.process_dir_path(Path::new(path)) {
+            let files = process_files(&dir).map_err(|source| ProcdError::ProcessDir { dir, source })?;
+            for file in files {
+                let pc: ProcessConfig = config::Config::builder()
+                    .add_source(config::File::from(file.as_path()))
+                    .build()
+                    .and_then(|s| s.try_deserialize())
+                    .map_err(config_error(&file.to_string_lossy()))?;
+                cfg.process.push(pc);
+            }
+        }
+
+        // 进程数量超过上限时整个配置都不加载，避免配置或自动化脚本出错时创建大量进程
+        if cfg.process.len() > cfg.max_processes {
+            return Err(ProcdError::TooManyProcesses {
+                count: cfg.process.len(),
+                max: cfg.max_processes,
+            });
+        }
 
         cfg.check_and_init();
         Ok(cfg)
     }
 
+    // 按 start_priority 从高到低排列的进程配置，优先级相同时保持配置中的顺序
+    pub fn start_order(&self) -> Vec<ProcessConfig> {
+        let mut list = self.process.clone();
+        list.sort_by_key(|p| std::cmp::Reverse(p.start_priority));
+        list
+    }
+
+    // process_dir 的路径，相对路径基于主配置文件所在目录
+    pub fn process_dir_path(&self, cfg_path: &Path) -> Option<PathBuf> {
+        let dir = self.process_dir.as_ref()?;
+        let base = cfg_path.parent().unwrap_or(Path::new(""));
+        Some(base.join(dir))
+    }
+
     pub fn set_current_dir(&self, cfg_path: &str) -> anyhow::Result<()> {
         // 切换工作目录到配置文件所在目录
         {
@@ -198,19 +805,465 @@ impl Config {
 
 use anyhow::bail;
 
+// 配置的语义错误，field 为出错的字段，如 process[web].memory_limit
+#[derive(Debug)]
+pub struct ConfigError {
+    pub field: String,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        ConfigError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+const MAX_MEMORY_MB: u32 = 1024 * 1024; // memory_limit 等内存配置的上限，1 TB
+pub const LOG_BUFFER_RANGE: std::ops::RangeInclusive<usize> = 256..=16 * 1024 * 1024; // log_buffer_bytes 的取值范围
+
+fn log_buffer_range_message() -> String {
+    format!(
+        "must be between {} and {} bytes",
+        LOG_BUFFER_RANGE.start(),
+        LOG_BUFFER_RANGE.end()
+    )
+}
+const MIN_MAX_RUN: Duration = Duration::from_secs(1); // max_run 过短会导致进程不停重启
+const MAX_INTERVAL: Duration = Duration::from_secs(365 * 24 * 3600); // max_run、next 的上限
+const WEB_ADDRESS_TOKENS: [&str; 4] = ["{HOST}", "{PORT}", "{PID}", "{NAME}"];
+
+// 目录可写，或者目录不存在但能在最近的已存在的上级目录中创建
+#[cfg(unix)]
+fn dir_writable(dir: &Path) -> bool {
+    use nix::unistd::{AccessFlags, access};
+    let mut dir = dir;
+    while !dir.exists() {
+        match dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => dir = parent,
+            _ => return access(".", AccessFlags::W_OK).is_ok(),
+        }
+    }
+    dir.is_dir() && access(dir, AccessFlags::W_OK).is_ok()
+}
+
+#[cfg(not(unix))]
+fn dir_writable(_dir: &Path) -> bool {
+    true
+}
+
+// check_command_allowed 检查 hook、健康检查等命令的程序是否在 allowed_commands 中，allowed 为空时不限制
+// 不含路径分隔符的命令名按 PATH 查找，相对路径基于 home
+pub fn check_command_allowed(allowed: &[String], program: &str, home: &str) -> Result<(), ProcdError> {
+    if allowed.is_empty() {
+        return Ok(());
+    }
+    let path = if program.contains(std::path::MAIN_SEPARATOR) {
+        Path::new(home).join(program)
+    } else {
+        which::which(program).map_err(|_| ProcdError::CommandNotFound {
+            cmd: program.to_string(),
+        })?
+    };
+    match path.canonicalize() {
+        Ok(resolved) => allowed_contains(allowed, &resolved),
+        Err(_) => Err(ProcdError::CommandNotFound {
+            cmd: path.to_string_lossy().to_string(),
+        }),
+    }
+}
+
+// 两边都 canonicalize 后比较，符号链接指向允许的程序时同样允许
+fn allowed_contains(allowed: &[String], resolved: &Path) -> Result<(), ProcdError> {
+    let found = allowed.iter().any(|c| {
+        let path = match Path::new(c).is_absolute() {
+            true => Ok(PathBuf::from(c)),
+            false => which::which(c),
+        };
+        path.ok()
+            .and_then(|p| p.canonicalize().ok())
+            .is_some_and(|p| p == resolved)
+    });
+    match found {
+        true => Ok(()),
+        false => Err(ProcdError::CommandNotAllowed {
+            cmd: resolved.to_string_lossy().to_string(),
+        }),
+    }
+}
+
+// 地址是否为 host:port 形式，IPv6 需要加方括号，如 [::1]:8090
+fn has_port(addr: &str) -> bool {
+    let Some((host, port)) = addr.rsplit_once(':') else {
+        return false;
+    };
+    let bracketed = host.starts_with('[') && host.ends_with(']');
+    !host.is_empty() && (bracketed || !host.contains(':')) && port.parse::<u16>().is_ok()
+}
+
+// 八进制的文件权限，可以写成字符串 "0600"、"0o600"，或数字 600（按八进制解释各位）
+fn deserialize_octal_mode<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Mode {
+        Text(String),
+        Number(u32),
+    }
+    let text = match Option::<Mode>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Mode::Text(s)) => s,
+        Some(Mode::Number(n)) => n.to_string(),
+    };
+    let digits = text.trim().trim_start_matches("0o");
+    u32::from_str_radix(digits, 8)
+        .ok()
+        .filter(|m| *m <= 0o777)
+        .map(Some)
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid file mode {:?}, use a quoted octal string like \"0600\"",
+                text
+            ))
+        })
+}
+
+// socket activation 的地址：unix:/path、以 / 开头的路径，或 host:port（可带 tcp: 前缀）
+fn valid_socket_addr(addr: &str) -> bool {
+    if let Some(path) = addr.strip_prefix("unix:") {
+        return path.starts_with('/');
+    }
+    addr.starts_with('/') || has_port(addr.strip_prefix("tcp:").unwrap_or(addr))
+}
+
+// Prometheus 的标签名称规则，以 __ 开头的名称保留给内部使用
+fn valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with("__")
+}
+
+// 机器的逻辑 CPU 数量，不受 procd 自身的 CPU 亲和性和 cgroup 配额影响
+fn cpu_count() -> usize {
+    use sysinfo::{CpuRefreshKind, RefreshKind, System};
+    System::new_with_specifics(RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing()))
+        .cpus()
+        .len()
+}
+
 impl ProcessConfig {
-    pub fn start_spawn(&self, reg: Arc<Registry>) {
+    // validate 检查单个进程的配置，返回 (字段, 错误信息) 列表
+    fn validate(&self, cpu_count: usize) -> Vec<(&'static str, String)> {
+        let mut errors = Vec::new();
+
+        for name in self.labels.keys() {
+            if !valid_label_name(name) {
+                errors.push((
+                    "labels",
+                    format!("{:?} must match [a-zA-Z_][a-zA-Z0-9_]* and not start with __", name),
+                ));
+            }
+        }
+        if self.cmd.is_empty() {
+            errors.push(("cmd", "must not be empty".to_string()));
+        } else if let Err(e) = self.check_allowed() {
+            errors.push(("cmd", e.to_string()));
+        }
+        let hooks = [
+            ("pre_start", &self.pre_start),
+            ("restart_cmd", &self.restart_cmd),
+            ("on_exit", &self.on_exit),
+            (
+                "health_check.cmd",
+                &self.health_check.as_ref().and_then(|hc| hc.cmd.clone()),
+            ),
+        ];
+        for (field, hook) in hooks {
+            if let Some(program) = hook.as_ref().and_then(|h| h.first())
+                && let Err(e) = check_command_allowed(&self.allowed_commands, program, &self.home)
+            {
+                errors.push((field, e.to_string()));
+            }
+        }
+        if self.arg0.is_some() && !self.sandbox.is_empty() {
+            errors.push(("arg0", "only works without sandbox".to_string()));
+        }
+        if let Some(encoding) = &self.encoding
+            && encoding_rs::Encoding::for_label(encoding.as_bytes()).is_none()
+        {
+            errors.push(("encoding", format!("unknown encoding {:?}", encoding)));
+        }
+        if let Some(size) = self.log_buffer_bytes
+            && !LOG_BUFFER_RANGE.contains(&size)
+        {
+            errors.push(("log_buffer_bytes", log_buffer_range_message()));
+        }
+        if let Some(mode) = self.log_mode_perms {
+            if cfg!(not(unix)) {
+                errors.push(("log_mode_perms", "only supported on unix".to_string()));
+            } else if mode & 0o600 != 0o600 {
+                errors.push((
+                    "log_mode_perms",
+                    format!("{:#o} must allow the owner to read and write", mode),
+                ));
+            }
+        }
+        if !self.env_passthrough.is_empty() && !self.env_clear {
+            errors.push(("env_passthrough", "only works with env_clear: true".to_string()));
+        }
+        if self.log_level.parse::<tracing::Level>().is_err() {
+            errors.push(("log_level", format!("invalid level {:?}", self.log_level)));
+        }
+
+        if let Some(limit) = self.memory_limit {
+            if limit == 0 || limit > MAX_MEMORY_MB {
+                errors.push(("memory_limit", format!("must be between 1 and {} MB", MAX_MEMORY_MB)));
+            }
+            if !self.sandbox.is_empty() {
+                errors.push(("memory_limit", "can not be used with sandbox".to_string()));
+            }
+        }
+        if let Some(limit) = self.restart_on_memory {
+            if limit == 0 || limit > MAX_MEMORY_MB {
+                errors.push((
+                    "restart_on_memory",
+                    format!("must be between 1 and {} MB", MAX_MEMORY_MB),
+                ));
+            }
+            if let Some(hard) = self.memory_limit
+                && limit >= hard
+            {
+                errors.push(("restart_on_memory", "must be less than memory_limit".to_string()));
+            }
+        }
+        if let Some(core) = self.cpu_affinity.iter().find(|c| **c >= cpu_count) {
+            errors.push((
+                "cpu_affinity",
+                format!("core {} out of range, only {} cpus", core, cpu_count),
+            ));
+        }
+
+        if let Some(ns) = &self.netns {
+            if ns.is_empty() || ns.contains('/') || ns == "." || ns == ".." {
+                errors.push(("netns", format!("invalid name {:?}", ns)));
+            } else if cfg!(target_os = "linux") && !Path::new("/var/run/netns").join(ns).exists() {
+                errors.push((
+                    "netns",
+                    format!("{} not found, create it with `ip netns add {}`", ns, ns),
+                ));
+            }
+        }
+
+        if let Some(addr) = &self.socket {
+            if cfg!(not(unix)) {
+                errors.push(("socket", "only supported on unix".to_string()));
+            } else if !valid_socket_addr(addr) {
+                errors.push((
+                    "socket",
+                    format!("invalid address {:?}, use host:port or unix:/path", addr),
+                ));
+            }
+            // 沙盒程序会再启动一层进程，LISTEN_PID 与实际进程不符
+            if !self.sandbox.is_empty() {
+                errors.push(("socket", "can not be used with sandbox".to_string()));
+            }
+        }
+
+        if let Some(root) = &self.chroot {
+            if cfg!(not(unix)) {
+                errors.push(("chroot", "only supported on unix".to_string()));
+            } else if !Path::new(root).is_absolute() || !Path::new(root).is_dir() {
+                errors.push(("chroot", format!("{} must be an existing absolute directory", root)));
+            }
+        }
+
+        if let Some(path) = &self.seccomp_profile {
+            #[cfg(all(target_os = "linux", feature = "seccomp"))]
+            if let Err(e) = process::seccomp::load_profile(path) {
+                errors.push(("seccomp_profile", format!("{:#}", e)));
+            }
+            #[cfg(not(all(target_os = "linux", feature = "seccomp")))]
+            errors.push((
+                "seccomp_profile",
+                format!("{}: requires linux and building with --features seccomp", path),
+            ));
+        }
+
+        if let Some(max_run) = self.max_run
+            && !(MIN_MAX_RUN..=MAX_INTERVAL).contains(&max_run)
+        {
+            errors.push(("max_run", format!("must be between {:?} and 365 days", MIN_MAX_RUN)));
+        }
+        if let Some(signal) = &self.max_run_signal {
+            #[cfg(unix)]
+            if parse_signal(signal).is_none() {
+                errors.push(("max_run_signal", format!("unknown signal {:?}", signal)));
+            }
+            #[cfg(windows)]
+            errors.push(("max_run_signal", format!("{}: only supported on unix", signal)));
+            if self.max_run.is_none() {
+                errors.push(("max_run_signal", "requires max_run".to_string()));
+            }
+        }
+        if let Some(next) = self.next
+            && next > MAX_INTERVAL
+        {
+            errors.push(("next", "must not be longer than 365 days".to_string()));
+        }
+
+        if let Some(timeout) = self.start_timeout {
+            if timeout.is_zero() {
+                errors.push(("start_timeout", "must be greater than 0".to_string()));
+            }
+            // 目前只有 ready_file 会让进程处于 Starting
+            if self.ready_file.is_none() {
+                errors.push(("start_timeout", "requires ready_file".to_string()));
+            }
+        }
+
+        if let Some(hc) = &self.health_check {
+            if hc.tcp.is_empty() && hc.cmd.is_none() {
+                errors.push(("health_check", "requires tcp or cmd".to_string()));
+            }
+            if hc.cmd.as_ref().is_some_and(|c| c.is_empty()) {
+                errors.push(("health_check.cmd", "must not be empty".to_string()));
+            }
+            if !hc.tcp.is_empty() && !has_port(&hc.tcp) {
+                errors.push(("health_check.tcp", format!("{:?} must be host:port", hc.tcp)));
+            }
+            if hc.interval.is_zero() {
+                errors.push(("health_check.interval", "must be greater than 0".to_string()));
+            }
+            if hc.failures == 0 {
+                errors.push(("health_check.failures", "must be greater than 0".to_string()));
+            }
+        }
+
+        if self.output_target("stdout").to_file() || self.output_target("stderr").to_file() {
+            if self.output_dir.is_empty() {
+                errors.push(("output_dir", "required when output is written to files".to_string()));
+            } else if !dir_writable(Path::new(&self.output_dir)) {
+                errors.push(("output_dir", format!("{} is not writable", self.output_dir)));
+            }
+        }
+
+        if !self.web_address.is_empty() {
+            let addr = &self.web_address;
+            if !addr.starts_with("http://") && !addr.starts_with("https://") && !addr.starts_with('/') {
+                errors.push(("web_address", "must start with http://, https:// or /".to_string()));
+            }
+            let rest = WEB_ADDRESS_TOKENS.iter().fold(addr.clone(), |s, t| s.replace(t, ""));
+            if rest.contains('{') || rest.contains('}') {
+                errors.push((
+                    "web_address",
+                    format!("unknown variable, supported: {}", WEB_ADDRESS_TOKENS.join(", ")),
+                ));
+            }
+        }
+
+        errors
+    }
+
+    // 判断当前主机名和环境变量是否满足 only_on_hosts 和 only_if_env 的限制
+    fn match_host_and_env(&self, hostname: &str) -> bool {
+        if !self.only_on_hosts.is_empty() && !self.only_on_hosts.iter().any(|h| h == hostname) {
+            return false;
+        }
+        self.only_if_env
+            .iter()
+            .all(|(k, v)| env::var(k).map(|val| &val == v).unwrap_or(false))
+    }
+
+    pub fn start_spawn(&self, reg: Arc<Registry>) -> tokio::task::JoinHandle<()> {
         let cfg = self.clone();
-        tokio::spawn(process::supervisor::supervise(cfg, reg));
+        tokio::spawn(process::supervisor::supervise(cfg, reg))
+    }
+
+    // check_allowed 检查实际要执行的命令（包括 cmd_fallbacks 选中的命令）是否在 allowed_commands 中
+    // 使用沙盒时真正执行的是沙盒程序，沙盒程序也必须在列表中；未配置 allowed_commands 时不限制
+    pub fn check_allowed(&self) -> Result<(), ProcdError> {
+        if self.allowed_commands.is_empty() {
+            return Ok(());
+        }
+        if let Some(program) = self.sandbox.first() {
+            check_command_allowed(&self.allowed_commands, program, &self.home)?;
+        }
+        let path = self.cmd_abs_path()?;
+        match path.canonicalize() {
+            Ok(resolved) => allowed_contains(&self.allowed_commands, &resolved),
+            Err(_) => Err(ProcdError::CommandNotFound {
+                cmd: path.to_string_lossy().to_string(),
+            }),
+        }
     }
 
     // cmd_abs_path 获取命令的绝对路径，不检查文件是否存在
     pub fn cmd_abs_path(&self) -> anyhow::Result<PathBuf> {
-        if self.cmd.trim().is_empty() {
+        self.host_path_of(&self.resolve_cmd()?)
+    }
+
+    // host_path_of 返回命令在 procd 所在文件系统中的路径；配置了 chroot 时，命令按 chroot 内的路径解析
+    fn host_path_of(&self, cmd: &str) -> anyhow::Result<PathBuf> {
+        let Some(root) = &self.chroot else {
+            return self.abs_path_of(cmd);
+        };
+        let root = Path::new(root);
+        let in_root = |p: &Path| root.join(p.strip_prefix("/").unwrap_or(p));
+
+        let cmd_path = Path::new(cmd);
+        if cmd_path.is_absolute() {
+            return anyhow::Ok(in_root(cmd_path));
+        }
+        if cmd.contains(std::path::MAIN_SEPARATOR) {
+            return anyhow::Ok(in_root(&Path::new(self.chroot_home()).join(cmd_path)));
+        }
+        // 纯命令名 → 在 chroot 内的 PATH 目录中查找
+        let paths = env::var_os("PATH").unwrap_or_default();
+        let paths = env::join_paths(env::split_paths(&paths).map(|p| in_root(&p)))?;
+        which::which_in(cmd, Some(paths), root)
+            .with_context(|| format!("failed to find '{}' in PATH under chroot", cmd))
+    }
+
+    // chroot 后的工作目录，即 chroot 内的 home，未配置时为 /
+    pub fn chroot_home(&self) -> &str {
+        if self.home.is_empty() { "/" } else { &self.home }
+    }
+
+    // 配置了 cmd_fallbacks 时，依次检查 cmd 和备选命令，返回第一个存在的
+    fn resolve_cmd(&self) -> anyhow::Result<String> {
+        if self.cmd_fallbacks.is_empty() {
+            return Ok(self.cmd.clone());
+        }
+
+        let candidates: Vec<&String> = std::iter::once(&self.cmd).chain(&self.cmd_fallbacks).collect();
+        for c in &candidates {
+            if self.host_path_of(c).is_ok_and(|p| p.exists()) {
+                return Ok(c.to_string());
+            }
+        }
+        bail!(
+            "none of the commands found: {}",
+            candidates.iter().map(|c| c.as_str()).collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn abs_path_of(&self, cmd: &str) -> anyhow::Result<PathBuf> {
+        if cmd.trim().is_empty() {
             bail!("cmd is empty");
         }
 
-        let cmd_path = Path::new(&self.cmd);
+        let cmd_path = Path::new(cmd);
 
         // 1️⃣ 绝对路径
         if cmd_path.is_absolute() {
@@ -225,21 +1278,57 @@ impl ProcessConfig {
         };
 
         // 2️⃣ 含路径分隔符（./app 或 bin/app）
-        if self.cmd.contains(std::path::MAIN_SEPARATOR) {
+        if cmd.contains(std::path::MAIN_SEPARATOR) {
             let abs = home_dir.join(cmd_path);
             return anyhow::Ok(abs);
         }
 
         // 3️⃣ 纯命令名 → 从 PATH 查找
-        let found = which::which_in(&self.cmd, env::var_os("PATH"), &home_dir)
-            .with_context(|| format!("failed to find '{}' in PATH", self.cmd))?;
+        let found = which::which_in(cmd, env::var_os("PATH"), &home_dir)
+            .with_context(|| format!("failed to find '{}' in PATH", cmd))?;
 
         anyhow::Ok(found)
     }
 
-    pub fn get_cmd(&self) -> std::process::Command {
+    // output_encoding 返回需要转码的输出编码；未配置、无法识别或本身就是 UTF-8 时返回 None
+    pub fn output_encoding(&self) -> Option<&'static encoding_rs::Encoding> {
+        let encoding = encoding_rs::Encoding::for_label(self.encoding.as_ref()?.as_bytes())?;
+        (encoding != encoding_rs::UTF_8).then_some(encoding)
+    }
+
+    // output_target 返回 stdout 或 stderr 的去向，未单独配置时沿用 redirect_output 的行为
+    pub fn output_target(&self, kind: &str) -> OutputTarget {
+        let target = if kind == "stderr" {
+            self.stderr_target
+        } else {
+            self.stdout_target
+        };
+        target.unwrap_or(if self.redirect_output {
+            OutputTarget::Both
+        } else {
+            OutputTarget::Log
+        })
+    }
+
+    pub fn output_level(&self) -> tracing::Level {
+        self.log_level.parse().unwrap_or_else(|_| {
+            tracing::warn!("invalid log_level={}, use debug", self.log_level);
+            tracing::Level::DEBUG
+        })
+    }
+
+    pub fn resolved_envs(&self) -> anyhow::Result<Vec<(String, String)>> {
+        resolve_envs(&self.envs)
+    }
+
+    pub fn get_cmd(&self) -> anyhow::Result<std::process::Command> {
+        let cmd = self.resolve_cmd()?;
+        if cmd != self.cmd {
+            tracing::info!("{}: cmd {} not found, using fallback {}", self.name, self.cmd, cmd);
+        }
+
         let mut args = self.sandbox.clone();
-        args.push(self.cmd.clone());
+        args.push(cmd);
         for a in &self.args.clone() {
             args.push(a.clone());
         }
@@ -247,6 +1336,8 @@ impl ProcessConfig {
         let mut app_home: String = env::current_dir().unwrap().to_string_lossy().to_string();
         if !self.home.is_empty() {
             app_home = self.home.clone();
+        } else if self.chroot.is_some() {
+            app_home = "/".to_string();
         }
 
         let mut has_replace = false;
@@ -257,20 +1348,58 @@ impl ProcessConfig {
             }
         }
 
-        tracing::debug!("cmd: {}", args.clone().join(" "));
+        // 含路径分隔符的相对命令（./app、bin/app）按 home 解析成绝对路径，
+        // 相对路径是否按 current_dir 解析与平台有关，不能依赖
+        // 配置了 chroot 时路径在 chroot 内，不能按当前文件系统 canonicalize
+        let cmd_idx = self.sandbox.len();
+        let cmd_path = Path::new(&args[cmd_idx]);
+        if !cmd_path.is_absolute() && args[cmd_idx].contains(std::path::MAIN_SEPARATOR) {
+            let abs = Path::new(&app_home).join(cmd_path);
+            let abs = if self.chroot.is_some() {
+                abs
+            } else {
+                abs.canonicalize().unwrap_or(abs)
+            };
+            args[cmd_idx] = abs.to_string_lossy().to_string();
+        }
+
+        match &self.arg0 {
+            Some(arg0) => tracing::debug!("cmd: {} (argv[0]={})", args.clone().join(" "), arg0),
+            None => tracing::debug!("cmd: {}", args.clone().join(" ")),
+        }
 
         let mut cmd = std::process::Command::new(&args[0]);
         cmd.args(&args[1..]);
 
-        cmd.env("NO_COLOR", "1"); // 子进程不输出颜色
-        for env in &self.envs {
-            if let Some((key, value)) = env.split_once("=") {
-                cmd.env(key, value);
+        if let Some(arg0) = &self.arg0 {
+            #[cfg(unix)]
+            {
+                use std::os::unix::process::CommandExt;
+                // 使用沙盒时 args[0] 是沙盒程序，不能覆盖它的 argv[0]
+                if self.sandbox.is_empty() {
+                    cmd.arg0(arg0);
+                } else {
+                    tracing::warn!("{}: arg0={} is ignored when running in sandbox", self.name, arg0);
+                }
+            }
+            #[cfg(windows)]
+            tracing::warn!("{}: arg0={} is not supported on windows", self.name, arg0);
+        }
+
+        if self.env_clear {
+            cmd.env_clear();
+            for key in &self.env_passthrough {
+                if let Some(value) = env::var_os(key) {
+                    cmd.env(key, value);
+                }
             }
         }
-        if !has_replace && !self.home.is_empty() {
+        cmd.env("NO_COLOR", "1"); // 子进程不输出颜色
+        cmd.envs(self.resolved_envs()?);
+        // 配置了 chroot 时 home 是 chroot 内的目录，在 chroot 之后切换
+        if !has_replace && !self.home.is_empty() && self.chroot.is_none() {
             cmd.current_dir(&self.home);
         }
-        return cmd;
+        Ok(cmd)
     }
 }
@@ -0,0 +1,38 @@
+use std::path::PathBuf;
+
+// 加载配置和启动进程时可区分的错误，调用方可以按类型处理（如命令不存在和没有权限）
+// main 和 API 等最外层仍然使用 anyhow
+#[derive(Debug, thiserror::Error)]
+pub enum ProcdError {
+    // 配置文件无法读取或格式错误
+    #[error("failed to load config {path}: {source}")]
+    Config { path: String, source: config::ConfigError },
+
+    // process_dir 无法读取
+    #[error("failed to read process_dir {dir:?}: {source}")]
+    ProcessDir { dir: PathBuf, source: std::io::Error },
+
+    // 配置中的进程数量超过 max_processes
+    #[error("too many processes: {count} configured, max_processes is {max}")]
+    TooManyProcesses { count: usize, max: usize },
+
+    // 要执行的命令不存在
+    #[error("command not found: {cmd}")]
+    CommandNotFound { cmd: String },
+
+    // 命令不在 allowed_commands 中
+    #[error("command not allowed: {cmd} is not in allowed_commands")]
+    CommandNotAllowed { cmd: String },
+
+    // 没有执行命令或设置进程环境（netns、chroot 等）的权限
+    #[error("permission denied: {cmd}{hint}")]
+    PermissionDenied { cmd: String, hint: String },
+
+    // 其他 fork/exec 失败
+    #[error("failed to spawn {cmd}: {source}")]
+    Spawn { cmd: String, source: std::io::Error },
+
+    // 启动前的准备工作失败，如 netns、chroot、seccomp_profile、socket
+    #[error(transparent)]
+    Setup(#[from] anyhow::Error),
+}
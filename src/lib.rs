@@ -0,0 +1,6 @@
+// oh-procd 的各模块，main.rs 使用它们启动服务；benches 和 tests 也通过这里访问内部实现
+pub mod api;
+pub mod config;
+pub mod error;
+pub mod logger;
+pub mod process;
@@ -3,8 +3,12 @@ use std::{
     sync::{Arc, Mutex},
 };
 use tracing_subscriber::Layer;
+use tracing_subscriber::fmt::{self, MakeWriter};
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::{EnvFilter, layer::SubscriberExt};
 
+use crate::config::{Config, LogFormat};
+
 #[derive(Clone)]
 pub struct LogBuffer {
     buffer: Arc<Mutex<VecDeque<String>>>,
@@ -42,7 +46,7 @@ use tracing::field::{Field, Visit};
 impl<'a> Visit for StringVisitor<'a> {
     fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
         if !self.output.is_empty() {
-            self.output.push_str(" ");
+            self.output.push(' ');
         }
         // 如果字段名是 "message"，通常我们不打印 "message=" 前缀
         if field.name() == "message" {
@@ -88,7 +92,7 @@ where
             "[{}] {} [{}] {}: {}",
             count,
             now,
-            metadata.level().to_string(),
+            metadata.level(),
             metadata.target(),
             fields_string
         );
@@ -104,19 +108,41 @@ where
 //     tracing::subscriber::set_global_default(subscriber).unwrap();
 // }
 
+// 按配置的格式创建输出 procd 日志的 fmt layer；without_time 等会改变 layer 的类型，因此每种组合分别装箱
+fn fmt_layer<S, W>(cfg: &Config, ansi: bool, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer()
+        .with_ansi(ansi && cfg.log_format != LogFormat::Json)
+        .with_target(cfg.log_target)
+        .with_writer(writer);
+    match (cfg.log_format, cfg.log_timestamp) {
+        (LogFormat::Full, true) => layer.boxed(),
+        (LogFormat::Full, false) => layer.without_time().boxed(),
+        (LogFormat::Compact, true) => layer.compact().boxed(),
+        (LogFormat::Compact, false) => layer.compact().without_time().boxed(),
+        (LogFormat::Pretty, true) => layer.pretty().boxed(),
+        (LogFormat::Pretty, false) => layer.pretty().without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().boxed(),
+        (LogFormat::Json, false) => layer.json().without_time().boxed(),
+    }
+}
+
 // 在配置文件读取后，已经配置情况初始日志，日志多写
-pub fn init_tracing(log_dir: &str, lb: LogBuffer) -> Option<tracing_appender::non_blocking::WorkerGuard> {
+// 控制台和 procd.log 使用配置的 log_format，BufferLayer（/api/logs）始终是人可读的单行格式
+pub fn init_tracing(cfg: &Config, lb: LogBuffer) -> Option<tracing_appender::non_blocking::WorkerGuard> {
     use tracing_appender::non_blocking;
     use tracing_appender::rolling;
-    use tracing_subscriber::fmt;
     use tracing_subscriber::util::SubscriberInitExt;
 
-    let log_dir: &str = log_dir.trim();
+    let log_dir: &str = cfg.log_dir.trim();
 
     let log_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("trace,tower_http=trace"));
 
     // 控制台 layer（带颜色）
-    let console_layer = fmt::layer().with_ansi(true).with_target(true);
+    let console_layer = fmt_layer(cfg, true, std::io::stdout);
 
     let bf_layer = BufferLayer {
         buffer: lb.clone(),
@@ -134,7 +160,7 @@ pub fn init_tracing(log_dir: &str, lb: LogBuffer) -> Option<tracing_appender::no
         let file_appender = rolling::hourly(log_dir, "procd.log");
         let (file_writer, guard) = non_blocking(file_appender);
 
-        let file_layer = fmt::layer().with_ansi(false).with_writer(file_writer);
+        let file_layer = fmt_layer(cfg, false, file_writer);
 
         registry.with(file_layer).init();
 
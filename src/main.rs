@@ -1,15 +1,10 @@
-mod api;
-mod config;
-mod logger;
-mod process;
-
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing;
 
-use crate::process::registry;
 use clap::Parser;
+use oh_procd::process::registry;
+use oh_procd::{api, config, logger, process};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -17,6 +12,34 @@ struct Args {
     /// 配置文件路径
     #[arg(short, long, value_name = "c", default_value = "procd.yml")]
     pub config: String,
+
+    /// 运行 restart=never 的进程直到全部结束，然后以汇总的退出码退出，适合作为批处理容器的入口
+    #[arg(long)]
+    pub oneshot: bool,
+
+    /// 只检查配置文件，输出所有错误后退出，配置有误时退出码为 1
+    #[arg(long)]
+    pub check: bool,
+
+    /// 配置检查不通过时拒绝启动，默认只输出警告
+    #[arg(long)]
+    pub strict: bool,
+}
+
+// 汇总 oneshot 进程的退出码：全部正常退出时为 0，否则为第一个失败进程的退出码（没有退出码时为 1）
+fn oneshot_exit_code(reg: &registry::Registry, names: &[String]) -> i32 {
+    for name in names {
+        let state = reg.find(name).map(|pe| pe.state);
+        match state {
+            Some(registry::ProcState::Exited { code: Some(0), .. }) => {}
+            Some(registry::ProcState::Exited { code: Some(code), .. }) => return code,
+            other => {
+                tracing::warn!("oneshot process {} finished with {:?}", name, other);
+                return 1;
+            }
+        }
+    }
+    0
 }
 
 #[tokio::main]
@@ -31,7 +54,16 @@ async fn main() {
     let cfg_path = args.config.as_str();
     tracing::info!("using config {}", cfg_path);
 
-    let cfg = config::Config::from_file(cfg_path).unwrap();
+    let cfg = match config::Config::from_file(cfg_path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // reload 时重新读取配置文件，工作目录改变前记录它的绝对路径
+    let abs_cfg_path = std::fs::canonicalize(cfg_path).unwrap_or_else(|_| cfg_path.into());
 
     // 设置当前进程的工作目录
     if let Err(e) = cfg.set_current_dir(cfg_path) {
@@ -39,13 +71,42 @@ async fn main() {
         std::process::exit(1);
     }
 
+    // 此时 tracing 尚未初始化，检查结果直接输出到 stderr
+    let errors = cfg.validate();
+    for e in &errors {
+        eprintln!("config error: {}", e);
+    }
+    if args.check {
+        if errors.is_empty() {
+            println!("config {} is ok", cfg_path);
+        }
+        std::process::exit(if errors.is_empty() { 0 } else { 1 });
+    }
+    if args.strict && !errors.is_empty() {
+        eprintln!("{} config errors, refusing to start (--strict)", errors.len());
+        std::process::exit(1);
+    }
+
     let reg = Arc::new(registry::Registry::new());
     // Spawn process
-    for process_cfg in cfg.process.clone() {
-        process_cfg.start_spawn(reg.clone());
+    let mut oneshot_jobs = Vec::new();
+    for process_cfg in cfg.start_order() {
+        if !process_cfg.auto_start {
+            tracing::info!("{}: auto_start=false, waiting for manual start", process_cfg.name);
+            reg.register_idle(process_cfg);
+            continue;
+        }
+        let handle = process_cfg.start_spawn(reg.clone());
+        if args.oneshot && process_cfg.enable && process_cfg.restart == config::RestartPolicy::Never {
+            oneshot_jobs.push((process_cfg.name.clone(), handle));
+        }
+    }
+    if args.oneshot && oneshot_jobs.is_empty() {
+        eprintln!("--oneshot requires at least one enabled process with restart: never");
+        std::process::exit(2);
     }
 
-    let _guard = logger::init_tracing(&cfg.log_dir, log_buf.clone());
+    let _guard = logger::init_tracing(&cfg, log_buf.clone());
 
     let cfg_arc = Arc::new(cfg.clone());
 
@@ -56,18 +117,77 @@ async fn main() {
     // 启动后台，定时检查文件变化任务
     reg.clone().watch(cfg.restart_delay.unwrap_or(Duration::from_secs(0)));
 
+    // 启动后台，检查 supervise 循环的心跳
+    reg.clone().watchdog();
+
+    // 启动后台，定时检查所有子进程的内存总和，以及各进程的 restart_on_memory
+    process::stats::spawn_memory_sampler(reg.clone(), cfg.total_memory_limit, cfg.total_memory_action);
+
+    // 启动后台，所有进程保留的 stderr 行超过 total_log_buffer_bytes 时按 log_buffer_priority 裁剪
+    if let Some(budget) = cfg.total_log_buffer_bytes {
+        process::logger::spawn_tail_trimmer(reg.clone(), budget);
+    }
+
+    // 启动后台，记录 procd 自身的资源使用
+    let self_metrics = Arc::new(process::stats::SelfMetrics::default());
+    process::stats::spawn_self_sampler(self_metrics.clone());
+
+    // 通过 POST /api/reload 或 SIGHUP 重新加载配置
+    let reloader = process::reload::Reloader::new(abs_cfg_path.clone());
+    #[cfg(unix)]
+    reloader.clone().watch_sighup(reg.clone());
+    if let Some(dir) = cfg.process_dir_path(&abs_cfg_path) {
+        reloader.clone().watch_process_dir(reg.clone(), dir);
+    }
+
     // Set up web API
-    let app = api::handlers::build_router()
+    let app = api::handlers::build_router(&cfg)
         .layer(axum::Extension(reg.clone()))
+        .layer(axum::Extension(reloader))
+        .layer(axum::Extension(self_metrics))
         .layer(axum::Extension(cfg_arc))
         .layer(axum::Extension(state))
         .layer(axum::Extension(log_buf));
 
-    tracing::info!("Listening on {}", cfg.http.addr);
+    // 每个监听地址启动一个 server，共用同一个 router
+    let mut servers = Vec::new();
+    for addr in cfg.http.addr.list() {
+        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+        tracing::info!("Listening on {}", addr);
+
+        let app = app.clone();
+        servers.push(tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+        }));
+    }
+
+    if args.oneshot {
+        // 等待所有 oneshot 进程结束，然后以汇总的退出码退出
+        let mut names = Vec::new();
+        for (name, handle) in oneshot_jobs {
+            let _ = handle.await;
+            names.push(name);
+        }
+        let code = oneshot_exit_code(&reg, &names);
+        tracing::info!("oneshot processes finished, exit with {}", code);
+        drop(_guard);
+        std::process::exit(code);
+    }
 
-    let addr = &cfg.http.addr;
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
-        .await
-        .unwrap();
+    let serve = async {
+        for server in servers {
+            server.await.unwrap().unwrap();
+        }
+    };
+    // 收到 SIGTERM/SIGINT 时先停止全部进程、运行 on_shutdown，再退出；再次收到信号时立即退出
+    tokio::select! {
+        _ = serve => {}
+        signal = process::shutdown::wait_signal() => {
+            tokio::select! {
+                _ = process::shutdown::shutdown(&reg, &cfg, signal) => {}
+                signal = process::shutdown::wait_signal() => tracing::warn!("received {} again, exit now", signal),
+            }
+        }
+    }
+    drop(_guard);
 }
@@ -0,0 +1,152 @@
+use anyhow::Context;
+use rand::RngExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::time::Duration;
+
+use crate::config::{HealthCheckConfig, ProcessConfig};
+use crate::process::hook;
+use crate::process::registry::{Health, Registry};
+
+const HEALTH_OUTPUT_TAIL: usize = 1024; // exec 健康检查保留的输出长度（字节）
+
+// connect_tcp 解析地址后依次尝试连接解析出的每个地址，任一成功即返回 Ok
+// 支持 "127.0.0.1:80"、"[::1]:80" 和 "localhost:80"，DNS 解析也计入 timeout；解析失败按检查失败处理
+pub async fn connect_tcp(addr: &str, timeout: Duration) -> anyhow::Result<()> {
+    let connect = async {
+        let addrs: Vec<_> = tokio::net::lookup_host(addr)
+            .await
+            .with_context(|| format!("resolve {} failed", addr))?
+            .collect();
+        let mut last_err = anyhow::anyhow!("{} resolved to no address", addr);
+        for sock_addr in addrs {
+            match tokio::net::TcpStream::connect(sock_addr).await {
+                Ok(_) => return Ok(()),
+                Err(e) => last_err = anyhow::Error::new(e).context(format!("connect {} ({}) failed", addr, sock_addr)),
+            }
+        }
+        Err(last_err)
+    };
+    tokio::time::timeout(timeout, connect)
+        .await
+        .map_err(|_| anyhow::anyhow!("connect {} timeout after {:?}", addr, timeout))?
+}
+
+// tail 返回输出末尾不超过 HEALTH_OUTPUT_TAIL 字节的部分，从字符边界截断
+fn tail(output: &str) -> &str {
+    let output = output.trim();
+    let mut start = output.len().saturating_sub(HEALTH_OUTPUT_TAIL);
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    &output[start..]
+}
+
+// run_cmd 在进程的 home 和环境变量下运行检查命令，结果记录到 registry，退出码为 0 时返回 Ok
+async fn run_cmd(cfg: &ProcessConfig, cmd: &[String], timeout: Duration, registry: &Registry) -> anyhow::Result<()> {
+    let pid = registry.find(&cfg.name).and_then(|pe| pe.pid).unwrap_or(0);
+    let result = async {
+        let mut envs = cfg.resolved_envs()?;
+        envs.push(("PROCD_NAME".to_string(), cfg.name.clone()));
+        envs.push(("PROCD_PID".to_string(), pid.to_string()));
+        hook::run_output("health_check", cmd, &cfg.home, envs, timeout, &cfg.allowed_commands).await
+    }
+    .await;
+    match result {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            let text = tail(&text).to_string();
+            let code = output.status.code();
+            registry.set_health_cmd(&cfg.name, code, text.clone());
+            match code {
+                Some(0) => Ok(()),
+                Some(c) => Err(anyhow::anyhow!("check command exited with {}: {}", c, text)),
+                None => Err(anyhow::anyhow!("check command killed by {:?}: {}", output.status, text)),
+            }
+        }
+        Err(e) => {
+            registry.set_health_cmd(&cfg.name, None, format!("{:#}", e));
+            Err(e)
+        }
+    }
+}
+
+// probe 执行一次检查，配置的 tcp 和 cmd 都通过时返回 Ok
+async fn probe(cfg: &ProcessConfig, hc: &HealthCheckConfig, registry: &Registry) -> anyhow::Result<()> {
+    if !hc.tcp.is_empty() {
+        connect_tcp(&hc.tcp, hc.timeout).await?;
+    }
+    if let Some(cmd) = &hc.cmd {
+        run_cmd(cfg, cmd, hc.timeout, registry).await?;
+    }
+    Ok(())
+}
+
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// 等待 ready_file 出现（普通文件或 unix socket 均可），超时返回 false
+pub async fn wait_ready_file(path: &Path, timeout: Duration) -> bool {
+    tokio::time::timeout(timeout, async {
+        while !path.exists() {
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+// 等待 ready_file 在 since 之后被创建或修改；blue-green 重启时旧实例的 ready_file 仍然存在，据此判断新实例是否就绪
+pub async fn wait_ready_file_since(path: &Path, since: SystemTime, timeout: Duration) -> bool {
+    let updated = || {
+        std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .is_ok_and(|t| t >= since)
+    };
+    tokio::time::timeout(timeout, async {
+        while !updated() {
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    })
+    .await
+    .is_ok()
+}
+
+// 每次检查的间隔，在 interval 基础上增加最多 10% 的随机抖动，避免大量进程同时检查
+fn next_interval(interval: Duration) -> Duration {
+    let max_jitter = (interval.as_millis() / 10) as u64;
+    if max_jitter == 0 {
+        return interval;
+    }
+    interval + Duration::from_millis(rand::rng().random_range(0..=max_jitter))
+}
+
+// watch 周期性检查进程的健康状态并更新到 registry
+// 首次失败后标记为 Degraded，连续失败达到 failures 次后标记为 Unhealthy 并返回，由调用方重启进程
+pub async fn watch(cfg: &ProcessConfig, hc: &HealthCheckConfig, registry: Arc<Registry>) {
+    let name = &cfg.name;
+    let mut failures: u32 = 0;
+    loop {
+        tokio::time::sleep(next_interval(hc.interval)).await;
+
+        match probe(cfg, hc, &registry).await {
+            Ok(()) => {
+                if failures > 0 {
+                    tracing::info!("health check recovered after {} failures", failures);
+                }
+                failures = 0;
+                registry.set_health(name, Some(Health::Healthy));
+            }
+            Err(e) => {
+                failures += 1;
+                tracing::warn!("health check failed ({}/{}): {}", failures, hc.failures, e);
+                if failures >= hc.failures {
+                    registry.set_health(name, Some(Health::Unhealthy));
+                    return;
+                }
+                registry.set_health(name, Some(Health::Degraded));
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{Context, bail};
+use std::time::Duration;
+
+use crate::config::{Config, ProcessConfig, check_command_allowed, resolve_envs};
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60); // hook 命令最长运行时长
+
+// run_hook 在子进程的 home 和环境变量下运行 hook 命令，等待其结束并返回退出码，超时则杀死 hook 进程
+pub async fn run_hook(
+    cfg: &ProcessConfig,
+    kind: &str,
+    hook: &[String],
+    envs: &[(&str, String)],
+) -> anyhow::Result<i32> {
+    let mut all_envs = cfg.resolved_envs()?;
+    all_envs.extend(envs.iter().map(|(k, v)| (k.to_string(), v.clone())));
+    run_command(
+        &cfg.name,
+        kind,
+        hook,
+        &cfg.home,
+        all_envs,
+        HOOK_TIMEOUT,
+        &cfg.allowed_commands,
+    )
+    .await
+}
+
+// run_shutdown_hook 在 procd 退出前运行全局的 on_shutdown，使用全局的 home 和环境变量
+pub async fn run_shutdown_hook(cfg: &Config, signal: &str) {
+    let Some(hook) = &cfg.on_shutdown else {
+        return;
+    };
+    let result = match resolve_envs(&cfg.envs) {
+        Ok(mut envs) => {
+            envs.push(("PROCD_SIGNAL".to_string(), signal.to_string()));
+            run_command(
+                "procd",
+                "on_shutdown",
+                hook,
+                &cfg.home,
+                envs,
+                cfg.on_shutdown_timeout,
+                &cfg.allowed_commands,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(0) => {}
+        Ok(c) => tracing::warn!("on_shutdown hook exited with {}", c),
+        Err(e) => tracing::warn!("on_shutdown hook failed: {:?}", e),
+    }
+}
+
+async fn run_command(
+    name: &str,
+    kind: &str,
+    hook: &[String],
+    home: &str,
+    envs: Vec<(String, String)>,
+    timeout: Duration,
+    allowed: &[String],
+) -> anyhow::Result<i32> {
+    tracing::info!("run {} hook: {}", kind, hook.join(" "));
+    let output = run_output(kind, hook, home, envs, timeout, allowed).await?;
+    for (from, out) in [("stdout", &output.stdout), ("stderr", &output.stderr)] {
+        if !out.is_empty() {
+            tracing::debug!(from, name, "{} hook: {}", kind, String::from_utf8_lossy(out));
+        }
+    }
+    Ok(output.status.code().unwrap_or(-1))
+}
+
+// run_output 在 home 下运行命令并收集输出，超时则杀死命令；hook 和 exec 健康检查共用
+// allowed 为全局的 allowed_commands，不为空时命令也必须在其中
+pub async fn run_output(
+    kind: &str,
+    hook: &[String],
+    home: &str,
+    envs: Vec<(String, String)>,
+    timeout: Duration,
+    allowed: &[String],
+) -> anyhow::Result<std::process::Output> {
+    if hook.is_empty() {
+        bail!("{} hook is empty", kind);
+    }
+    check_command_allowed(allowed, &hook[0], home).with_context(|| format!("{} hook refused", kind))?;
+
+    let mut cmd = tokio::process::Command::new(&hook[0]);
+    cmd.args(&hook[1..]);
+    cmd.env("NO_COLOR", "1");
+    cmd.envs(envs);
+    if !home.is_empty() {
+        cmd.current_dir(home);
+    }
+    cmd.kill_on_drop(true); // 超时后 future 被 drop，同时杀死 hook 进程
+
+    let output = tokio::time::timeout(timeout, cmd.output())
+        .await
+        .with_context(|| format!("{} hook timeout after {:?}", kind, timeout))?
+        .with_context(|| format!("{} hook [ {} ] failed", kind, hook.join(" ")))?;
+    Ok(output)
+}
+
+// spawn_exit_hook 在后台运行 on_exit hook，不阻塞 supervise 的循环
+pub fn spawn_exit_hook(cfg: &ProcessConfig, code: i32, signal: Option<i32>, reason: &'static str) {
+    let Some(hook) = cfg.on_exit.clone() else {
+        return;
+    };
+    let cfg = cfg.clone();
+    tokio::spawn(async move {
+        let envs = [
+            ("PROCD_NAME", cfg.name.clone()),
+            ("PROCD_EXIT_CODE", code.to_string()),
+            ("PROCD_EXIT_REASON", reason.to_string()),
+            ("PROCD_EXIT_SIGNAL", signal.map(|s| s.to_string()).unwrap_or_default()),
+        ];
+        match run_hook(&cfg, "on_exit", &hook, &envs).await {
+            Ok(0) => {}
+            Ok(c) => tracing::warn!(name = cfg.name, "on_exit hook exited with {}", c),
+            Err(e) => tracing::warn!(name = cfg.name, "on_exit hook failed: {:?}", e),
+        }
+    });
+}
@@ -1,78 +1,751 @@
-use chrono::Local;
+use chrono::{Local, Utc};
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
 use std::{
+    borrow::Cow,
+    collections::VecDeque,
     fs::{self, OpenOptions},
     io::Write,
+    os::fd::AsFd,
     path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
 };
+use tracing::Level;
 
-use crate::config::ProcessConfig;
+use crate::config::{LOG_BUFFER_RANGE, LogMode, LogTimezone, ProcessConfig};
+use crate::process::registry::Registry;
 
-fn current_hour() -> String {
-    Local::now().format("%Y%m%d%H").to_string()
+const DEFAULT_READ_BUFFER: usize = 4096; // 未配置 log_buffer_bytes 时读取输出的缓冲区大小
+const SYNC_INTERVAL: Duration = Duration::from_secs(2); // 日志文件落盘(fsync)、输出未结束的行的周期
+const LOG_RETRY_INTERVAL: Duration = Duration::from_secs(30); // 写入日志文件失败后，再次尝试打开文件的间隔
+
+const LEVELS: [Level; 5] = [Level::TRACE, Level::DEBUG, Level::INFO, Level::WARN, Level::ERROR];
+
+// 子进程输出写入 tracing 时使用的日志级别，多个读取线程共享，可在运行时修改
+#[derive(Clone, Debug)]
+pub struct OutputLevel(Arc<AtomicU8>);
+
+impl OutputLevel {
+    pub fn new(level: Level) -> Self {
+        let out = OutputLevel(Arc::new(AtomicU8::new(0)));
+        out.set(level);
+        out
+    }
+
+    pub fn get(&self) -> Level {
+        LEVELS[self.0.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set(&self, level: Level) {
+        let idx = LEVELS.iter().position(|l| *l == level).unwrap_or(1);
+        self.0.store(idx as u8, Ordering::Relaxed);
+    }
+}
+
+const TAIL_LINES: usize = 20; // 保留最近多少行 stderr 输出
+const TAIL_LINE_MAX: usize = 1024; // 单行最多保留的字节数，超出部分截断
+const TAIL_TRIM_INTERVAL: Duration = Duration::from_secs(2); // 检查 total_log_buffer_bytes 的间隔
+
+// 最近的若干行输出，用于进程崩溃后快速查看错误信息
+#[derive(Clone, Debug, Default)]
+pub struct TailLines(Arc<Mutex<VecDeque<String>>>);
+
+impl TailLines {
+    fn push(&self, line: &[u8]) {
+        let line = &line[..line.len().min(TAIL_LINE_MAX)];
+        let line = String::from_utf8_lossy(line).trim_end().to_string();
+        let mut buf = self.0.lock().unwrap();
+        if buf.len() == TAIL_LINES {
+            buf.pop_front();
+        }
+        buf.push_back(line);
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+
+    // 保留的字节数
+    pub fn bytes(&self) -> usize {
+        self.0.lock().unwrap().iter().map(|l| l.len()).sum()
+    }
+
+    // 从最旧的行开始删除，直到释放至少 want 字节或者删空，返回释放的字节数
+    fn trim(&self, want: usize) -> usize {
+        let mut buf = self.0.lock().unwrap();
+        let mut freed = 0;
+        while freed < want
+            && let Some(line) = buf.pop_front()
+        {
+            freed += line.len();
+        }
+        freed
+    }
+}
+
+// 所有进程保留的 stderr 行超过 budget 字节时裁剪：log_buffer_priority 低的先裁剪，同一优先级中占用多的先裁剪
+// buffers 为各进程的 (名称, log_buffer_priority, stderr_tail)
+fn trim_tail_buffers(buffers: &[(String, u8, TailLines)], budget: usize) {
+    let mut sized: Vec<(&str, u8, usize, &TailLines)> = buffers
+        .iter()
+        .map(|(name, priority, tail)| (name.as_str(), *priority, tail.bytes(), tail))
+        .collect();
+    let mut total: usize = sized.iter().map(|b| b.2).sum();
+    if total <= budget {
+        return;
+    }
+    sized.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.cmp(&a.2)));
+    for (name, priority, _, tail) in sized {
+        let freed = tail.trim(total - budget);
+        if freed > 0 {
+            tracing::debug!("{}: trimmed {} bytes of stderr tail (priority {})", name, freed, priority);
+        }
+        total -= freed;
+        if total <= budget {
+            break;
+        }
+    }
+}
+
+// 定期检查所有进程保留的 stderr 行，超过 total_log_buffer_bytes 时裁剪
+pub fn spawn_tail_trimmer(reg: Arc<Registry>, budget: usize) {
+    tracing::info!("total_log_buffer_bytes {}", budget);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(TAIL_TRIM_INTERVAL).await;
+            trim_tail_buffers(&reg.stderr_tails(), budget);
+        }
+    });
+}
+
+// 当前正在写入的 stdout、stderr 日志文件的绝对路径，打开或按小时切换文件时更新
+#[derive(Clone, Debug, Default)]
+pub struct CurrentLogFiles(Arc<Mutex<[Option<String>; 2]>>);
+
+impl CurrentLogFiles {
+    fn set(&self, kind: &str, path: &Path) {
+        let abs = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let idx = usize::from(kind == "stderr");
+        self.0.lock().unwrap()[idx] = Some(abs.to_string_lossy().to_string());
+    }
+
+    pub fn stdout(&self) -> Option<String> {
+        self.0.lock().unwrap()[0].clone()
+    }
+
+    pub fn stderr(&self) -> Option<String> {
+        self.0.lock().unwrap()[1].clone()
+    }
+}
+
+// stdout、stderr 的日志文件是否可写；写入失败时置为 false，之后重新打开文件并写入成功时恢复
+#[derive(Clone, Debug)]
+pub struct LogWritable(Arc<[AtomicBool; 2]>);
+
+impl Default for LogWritable {
+    fn default() -> Self {
+        LogWritable(Arc::new([AtomicBool::new(true), AtomicBool::new(true)]))
+    }
+}
+
+impl LogWritable {
+    fn set(&self, kind: &str, writable: bool) {
+        self.0[usize::from(kind == "stderr")].store(writable, Ordering::Relaxed);
+    }
+
+    // stdout 和 stderr 都可写时返回 true
+    pub fn get(&self) -> bool {
+        self.0.iter().all(|w| w.load(Ordering::Relaxed))
+    }
+}
+
+const RATE_WINDOW: Duration = Duration::from_secs(10); // 计算输出速率的时间窗口
+
+// 输出的行数统计，stdout 和 stderr 共用；全部使用原子变量，读取输出时不加锁
+#[derive(Debug)]
+pub struct LineStats {
+    epoch: Instant,
+    total: AtomicU64,
+    window_start_ms: AtomicU64, // 当前窗口的开始时间，相对 epoch 的毫秒数
+    window_lines: AtomicU64,    // 当前窗口内的行数
+    last_rate: AtomicU64,       // 上一个完整窗口的速率，f64 的位表示
+}
+
+impl LineStats {
+    fn new() -> Self {
+        LineStats {
+            epoch: Instant::now(),
+            total: AtomicU64::new(0),
+            window_start_ms: AtomicU64::new(0),
+            window_lines: AtomicU64::new(0),
+            last_rate: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.epoch.elapsed().as_millis() as u64
+    }
+
+    fn add(&self, lines: u64) {
+        self.total.fetch_add(lines, Ordering::Relaxed);
+        self.window_lines.fetch_add(lines, Ordering::Relaxed);
+
+        let now = self.now_ms();
+        let start = self.window_start_ms.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(start);
+        // 只有切换窗口成功的一方负责计算速率
+        if elapsed >= RATE_WINDOW.as_millis() as u64
+            && self
+                .window_start_ms
+                .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            let lines = self.window_lines.swap(0, Ordering::Relaxed);
+            let rate = lines as f64 * 1000.0 / elapsed as f64;
+            self.last_rate.store(rate.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    // 最近的每秒行数；长时间没有输出时，当前窗口的速率会逐渐降低
+    pub fn rate(&self) -> f64 {
+        let elapsed = self
+            .now_ms()
+            .saturating_sub(self.window_start_ms.load(Ordering::Relaxed));
+        if elapsed >= RATE_WINDOW.as_millis() as u64 {
+            return self.window_lines.load(Ordering::Relaxed) as f64 * 1000.0 / elapsed as f64;
+        }
+        f64::from_bits(self.last_rate.load(Ordering::Relaxed))
+    }
+}
+
+// 子进程输出相关的共享状态，由 registry 和读取输出的线程共同持有
+#[derive(Clone, Debug)]
+pub struct OutputState {
+    pub level: OutputLevel,         // 输出写入日志的级别
+    pub stderr_tail: TailLines,     // 最近的 stderr 行
+    pub line_stats: Arc<LineStats>, // 输出的行数统计
+    pub log_files: CurrentLogFiles, // 当前写入的日志文件
+    pub log_writable: LogWritable,  // 日志文件是否可写
+}
+
+impl OutputState {
+    pub fn new(cfg: &ProcessConfig) -> Self {
+        OutputState {
+            level: OutputLevel::new(cfg.output_level()),
+            stderr_tail: TailLines::default(),
+            line_stats: Arc::new(LineStats::new()),
+            log_files: CurrentLogFiles::default(),
+            log_writable: LogWritable::default(),
+        }
+    }
+}
+
+// 将读取到的数据块拆分成行，未结束的行保留到下次，超长的行按 max（默认 TAIL_LINE_MAX）强制拆分
+struct LineSplitter {
+    partial: Vec<u8>,
+    max: usize,
+}
+
+impl Default for LineSplitter {
+    fn default() -> Self {
+        LineSplitter::with_max(TAIL_LINE_MAX)
+    }
+}
+
+impl LineSplitter {
+    fn with_max(max: usize) -> Self {
+        LineSplitter {
+            partial: Vec::new(),
+            max,
+        }
+    }
+
+    fn push(&mut self, data: &[u8], mut on_line: impl FnMut(&[u8])) {
+        for segment in data.split_inclusive(|b| *b == b'\n') {
+            self.partial.extend_from_slice(segment);
+            if segment.ends_with(b"\n") || self.partial.len() >= self.max {
+                on_line(&self.partial);
+                self.partial.clear();
+            }
+        }
+    }
+
+    fn finish(&mut self, mut on_line: impl FnMut(&[u8])) {
+        if !self.partial.is_empty() {
+            on_line(&self.partial);
+            self.partial.clear();
+        }
+    }
+}
+
+// 流式解码为 UTF-8：多字节字符被拆分到两次读取中时，未完成的字节保留到下次，不会被替换成乱码
+struct StreamDecoder(encoding_rs::Decoder);
+
+impl StreamDecoder {
+    fn new(encoding: &'static encoding_rs::Encoding) -> Self {
+        StreamDecoder(encoding.new_decoder_without_bom_handling())
+    }
+
+    // last 为 true 时表示数据已结束，之前保留的不完整字节输出为替换字符
+    fn decode(&mut self, data: &[u8], last: bool) -> String {
+        let mut s = String::with_capacity(self.0.max_utf8_buffer_length(data.len()).unwrap_or(data.len() * 3 + 3));
+        let _ = self.0.decode_to_string(data, &mut s, last);
+        s
+    }
+}
+
+const TRUNCATED_MARKER: &[u8] = b"...[truncated]";
+
+// 限制单行输出的长度，超出 max 的部分丢弃到下一个换行符为止，并在截断处追加标记
+// 子进程输出不带换行的超长内容时，写入文件和 procd 日志的数据不会无限增长
+struct LineLimiter {
+    max: Option<usize>,
+    line_len: usize, // 当前行已保留的字节数
+    truncated: bool, // 当前行是否已截断
+}
+
+impl LineLimiter {
+    fn new(max: Option<usize>) -> Self {
+        LineLimiter {
+            max: max.filter(|m| *m > 0),
+            line_len: 0,
+            truncated: false,
+        }
+    }
+
+    fn apply<'a>(&mut self, data: &'a [u8]) -> Cow<'a, [u8]> {
+        let Some(max) = self.max else {
+            return Cow::Borrowed(data);
+        };
+        // 常见情况：没有超长的行，不复制数据
+        let mut len = self.line_len;
+        let fits = !self.truncated
+            && data.split_inclusive(|b| *b == b'\n').all(|seg| {
+                let fits = len + seg.len() - usize::from(seg.ends_with(b"\n")) <= max;
+                len = if seg.ends_with(b"\n") { 0 } else { len + seg.len() };
+                fits
+            });
+        if fits {
+            self.line_len = len;
+            return Cow::Borrowed(data);
+        }
+
+        let mut out = Vec::with_capacity(data.len().min(max + TRUNCATED_MARKER.len() + 1));
+        for seg in data.split_inclusive(|b| *b == b'\n') {
+            let newline = seg.ends_with(b"\n");
+            let content = &seg[..seg.len() - usize::from(newline)];
+            if !self.truncated {
+                let keep = content.len().min(max - self.line_len);
+                out.extend_from_slice(&content[..keep]);
+                self.line_len += keep;
+                if keep < content.len() {
+                    out.extend_from_slice(TRUNCATED_MARKER);
+                    self.truncated = true;
+                }
+            }
+            if newline {
+                out.push(b'\n');
+                self.line_len = 0;
+                self.truncated = false;
+            }
+        }
+        Cow::Owned(out)
+    }
+}
+
+// 所有进程 stdout、stderr 输出行共用的递增序号，两个读取线程各自取号，用于合并时尽量还原输出的先后顺序
+static OUTPUT_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// 在每行开头加上序号 "#<seq> "；上一块数据中未结束的行继续写入，不再加序号
+#[derive(Default)]
+struct SeqPrefixer {
+    in_line: bool, // 上一块数据的最后一行还没有结束
+}
+
+impl SeqPrefixer {
+    fn apply(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 16);
+        for seg in data.split_inclusive(|b| *b == b'\n') {
+            if !self.in_line {
+                let seq = OUTPUT_SEQ.fetch_add(1, Ordering::Relaxed);
+                out.extend_from_slice(format!("#{} ", seq).as_bytes());
+            }
+            out.extend_from_slice(seg);
+            self.in_line = !seg.ends_with(b"\n");
+        }
+        out
+    }
+}
+
+// 解析 SeqPrefixer 写入的序号前缀，返回序号和去掉前缀的内容
+pub fn parse_seq_prefix(line: &[u8]) -> Option<(u64, &[u8])> {
+    let rest = line.strip_prefix(b"#")?;
+    let end = rest.iter().position(|b| *b == b' ')?;
+    let seq = std::str::from_utf8(&rest[..end]).ok()?.parse().ok()?;
+    Some((seq, &rest[end + 1..]))
+}
+
+const JSON_LINE_MAX: usize = 1024 * 1024; // 解析 JSON 日志时单行的最大字节数，超出时强制拆分（不再是合法 JSON，按原样输出）
+
+// JSON 日志中的级别：字符串（info、WARN、warning 等）或 pino 风格的数字（10~60）
+fn json_level(value: &serde_json::Value) -> Option<Level> {
+    match value {
+        serde_json::Value::String(s) => match s.to_ascii_lowercase().as_str() {
+            "trace" => Some(Level::TRACE),
+            "debug" => Some(Level::DEBUG),
+            "info" | "notice" => Some(Level::INFO),
+            "warn" | "warning" => Some(Level::WARN),
+            "error" | "err" | "fatal" | "panic" | "critical" | "crit" | "alert" | "emerg" => Some(Level::ERROR),
+            _ => None,
+        },
+        serde_json::Value::Number(n) => match n.as_u64()? {
+            0..=10 => Some(Level::TRACE),
+            11..=20 => Some(Level::DEBUG),
+            21..=30 => Some(Level::INFO),
+            31..=40 => Some(Level::WARN),
+            _ => Some(Level::ERROR),
+        },
+        _ => None,
+    }
+}
+
+// 解析一行 JSON 日志，取出级别和消息，其余字段以紧凑的 JSON 附在消息后；不是 JSON 对象时返回 None
+// message_field 未配置时依次尝试 msg、message
+fn parse_json_line(line: &str, level_field: &str, message_field: Option<&str>) -> Option<(Option<Level>, String)> {
+    let serde_json::Value::Object(mut obj) = serde_json::from_str(line).ok()? else {
+        return None;
+    };
+    let level = obj.get(level_field).and_then(json_level);
+    if level.is_some() {
+        obj.remove(level_field);
+    }
+    let message = match message_field {
+        Some(field) => obj.remove(field),
+        None => obj.remove("msg").or_else(|| obj.remove("message")),
+    };
+    let mut text = match message {
+        Some(serde_json::Value::String(s)) => s,
+        Some(v) => v.to_string(),
+        None => String::new(),
+    };
+    if !obj.is_empty() {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(&serde_json::Value::Object(obj).to_string());
+    }
+    Some((level, text))
+}
+
+// 按 JSON 日志的级别输出一行；解析失败或没有级别时使用 default_level
+fn emit_json_line(cfg: &ProcessConfig, default_level: Level, kind: &str, pid: u32, line: &[u8]) {
+    let line = String::from_utf8_lossy(line);
+    let line = line.trim_end();
+    if line.is_empty() {
+        return;
+    }
+    match parse_json_line(line, &cfg.log_json_level_field, cfg.log_json_message_field.as_deref()) {
+        Some((level, text)) => emit(level.unwrap_or(default_level), kind, pid, &cfg.name, &text),
+        None => emit(default_level, kind, pid, &cfg.name, line),
+    }
+}
+
+fn emit(level: Level, kind: &str, pid: u32, name: &str, s: &str) {
+    match level {
+        Level::TRACE => tracing::trace!(from = kind, pid = pid, name = name, "{}", s),
+        Level::DEBUG => tracing::debug!(from = kind, pid = pid, name = name, "{}", s),
+        Level::INFO => tracing::info!(from = kind, pid = pid, name = name, "{}", s),
+        Level::WARN => tracing::warn!(from = kind, pid = pid, name = name, "{}", s),
+        Level::ERROR => tracing::error!(from = kind, pid = pid, name = name, "{}", s),
+    }
+}
+
+pub fn current_hour(tz: LogTimezone) -> String {
+    match tz {
+        LogTimezone::Local => Local::now().format("%Y%m%d%H").to_string(),
+        LogTimezone::Utc => Utc::now().format("%Y%m%d%H").to_string(),
+    }
+}
+
+// 当前小时正在写入的日志文件名，如 stdout.2024010112.log，和 pipe_logger 的命名一致
+pub fn current_log_file_name(kind: &str, tz: LogTimezone) -> String {
+    format!("{kind}.{}.log", current_hour(tz))
 }
 
-pub fn pipe_logger(mut reader: impl std::io::Read + Send + 'static, cfg: ProcessConfig, pid: u32, kind: &'static str) {
+// 创建日志目录；配置了 log_mode_perms 时，新建的各级目录使用该权限，有读写权限的位同时加上执行位，否则无法进入目录
+fn create_log_dir(dir: &Path, perms: Option<u32>) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = perms {
+        use std::os::unix::fs::DirBuilderExt;
+        let dir_mode = [6, 3, 0]
+            .iter()
+            .filter(|shift| (mode >> **shift) & 0o6 != 0)
+            .fold(mode, |m, shift| m | (1 << shift));
+        return fs::DirBuilder::new().recursive(true).mode(dir_mode).create(dir);
+    }
+    fs::create_dir_all(dir)
+}
+
+// 打开日志文件，truncate 为 false 时追加；log_mode_perms 只作用于新建的文件（仍受 umask 限制）
+fn open_log_file(path: &Path, truncate: bool, perms: Option<u32>) -> std::io::Result<fs::File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+    if truncate {
+        options.write(true).truncate(true);
+    } else {
+        options.append(true);
+    }
+    #[cfg(unix)]
+    if let Some(mode) = perms {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+    #[cfg(not(unix))]
+    let _ = perms;
+    options.open(path)
+}
+
+// 一块输出写入日志文件的结果
+enum LogWrite {
+    Skipped,        // 没有数据或不需要写入文件
+    Written,        // 已写入
+    Failed(String), // 创建目录、打开或写入文件失败
+    Suspended,      // 之前失败过，还没到重试的时间
+}
+
+// 等待 reader 可读，最多等待 timeout，超时返回 false；被信号中断时同样返回 false，由调用方重新计算等待时间
+// 出错时返回 true，交给后面的 read 报告错误
+fn wait_readable(reader: &impl AsFd, timeout: Duration) -> bool {
+    let mut fds = [PollFd::new(reader.as_fd(), PollFlags::POLLIN)];
+    match poll(&mut fds, PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX)) {
+        Ok(n) => n > 0,
+        Err(Errno::EINTR) => false,
+        Err(_) => true,
+    }
+}
+
+pub fn pipe_logger(
+    mut reader: impl std::io::Read + AsFd + Send + 'static,
+    cfg: ProcessConfig,
+    pid: u32,
+    kind: &'static str,
+    output: OutputState,
+) {
     std::thread::spawn(move || {
-        let mut buf = [0u8; 4096];
+        // 配置检查不通过时仍会启动，超出范围的值在这里限制，0 会被当作 EOF
+        let size = cfg.log_buffer_bytes.map_or(DEFAULT_READ_BUFFER, |n| {
+            n.clamp(*LOG_BUFFER_RANGE.start(), *LOG_BUFFER_RANGE.end())
+        });
+        let mut buf = vec![0u8; size];
+        let mut splitter = LineSplitter::default();
+        let mut limiter = LineLimiter::new(cfg.max_log_line);
+        let mut transcoder = cfg.output_encoding().map(StreamDecoder::new);
+        let mut log_decoder = StreamDecoder::new(encoding_rs::UTF_8);
+        let mut seq_prefixer = cfg.log_seq.then(SeqPrefixer::default);
+        // 解析 JSON 日志时需要按行处理，max_log_line 已限制行长时不会被强制拆分
+        let mut json_splitter = cfg.log_json_passthrough.then(|| {
+            let max = limiter.max.map_or(JSON_LINE_MAX, |m| m + TRUNCATED_MARKER.len() + 1);
+            LineSplitter::with_max(max)
+        });
 
         let mut file: Option<std::fs::File> = None;
-        let mut active_hour = current_hour();
+        let mut active_hour = current_hour(cfg.log_timezone);
+        let mut next_tick = Instant::now() + SYNC_INTERVAL;
+        let mut unsynced = false; // 上次落盘后是否写入过文件
+        let mut received = false; // 这个周期内是否读到过数据
+        let mut retry_at: Option<Instant> = None; // 写入日志文件失败后，下次尝试的时间
+        let target = cfg.output_target(kind);
+
+        // truncate 模式下，进程每次启动时立即清空日志文件，即使这次运行没有输出，也不会留下上次运行的内容
+        // 之后按小时切分、或文件被删除后重新打开时都使用追加
+        if cfg.log_mode == LogMode::Truncate && target.to_file() && !cfg.output_dir.is_empty() {
+            let dir = Path::new(&cfg.output_dir);
+            let path = dir.join(format!("{kind}.{active_hour}.log"));
+            match create_log_dir(dir, cfg.log_mode_perms).and_then(|_| open_log_file(&path, true, cfg.log_mode_perms)) {
+                Ok(f) => {
+                    file = Some(f);
+                    output.log_files.set(kind, &path);
+                    tracing::info!("open_log {:?} (truncated)", &path);
+                }
+                Err(e) => tracing::warn!("truncate log failed {:?}", e),
+            }
+        }
 
         loop {
-            let n = match reader.read(&mut buf) {
-                Ok(0) => break, //  EOF
-                Ok(n) => n,
+            // 定时落盘，子进程没有输出时也按 SYNC_INTERVAL 醒来，不依赖下一块数据的到达
+            if Instant::now() >= next_tick {
+                if unsynced
+                    && let Some(f) = file.as_ref()
+                    && let Err(e) = f.sync_data()
+                {
+                    tracing::warn!("sync log failed: {:?}", e);
+                }
+                unsynced = false;
+                // 整个周期都没有新的输出时，输出缓冲中未结束的行，如等待输入的提示符、最后一条没有换行的 JSON 日志；
+                // 仍在持续输出的行不会被拆开
+                if !received {
+                    if let Some(json) = json_splitter.as_mut() {
+                        json.finish(|line| emit_json_line(&cfg, output.level.get(), kind, pid, line));
+                    }
+                    splitter.finish(|line| output.stderr_tail.push(line));
+                }
+                received = false;
+                next_tick = Instant::now() + SYNC_INTERVAL;
+            }
+            if !wait_readable(&reader, next_tick.saturating_duration_since(Instant::now())) {
+                continue;
+            }
+
+            // EOF 或读取失败时同样走一遍下面的流程：输出各个缓冲中剩余的数据（没有换行的最后一行、不完整的多字节字符），
+            // 写入文件并落盘后再退出，进程崩溃前的最后输出不会丢失
+            let (n, eof) = match reader.read(&mut buf) {
+                Ok(0) => (0, true),
+                Ok(n) => {
+                    received = true;
+                    (n, false)
+                }
                 Err(e) => {
                     tracing::warn!("read pipe failed: {:?}", e);
-                    break;
+                    (0, true)
                 }
             };
 
+            // 配置了 encoding 时先转为 UTF-8，之后写入文件和日志的都是 UTF-8
+            let transcoded = transcoder.as_mut().map(|d| d.decode(&buf[..n], eof));
+            let data = limiter.apply(transcoded.as_ref().map_or(&buf[..n], |s| s.as_bytes()));
+
             // 额外往 tracing 输出一份
-            {
-                let s = String::from_utf8_lossy(&buf[..n]);
-                tracing::debug!(from = kind, pid = pid, name = &cfg.name, "{}", &s);
+            if target.to_log() {
+                let s = log_decoder.decode(&data, eof);
+                match json_splitter.as_mut() {
+                    Some(json) => {
+                        let mut on_line = |line: &[u8]| emit_json_line(&cfg, output.level.get(), kind, pid, line);
+                        json.push(s.as_bytes(), &mut on_line);
+                        if eof {
+                            json.finish(on_line);
+                        }
+                    }
+                    None if !s.is_empty() => emit(output.level.get(), kind, pid, &cfg.name, &s),
+                    None => {}
+                }
             }
 
-            if !cfg.redirect_output || cfg.output_dir.is_empty() {
-                continue;
-            }
+            output
+                .line_stats
+                .add(data.iter().filter(|b| **b == b'\n').count() as u64);
 
-            let dir = Path::new(&cfg.output_dir);
-            if !dir.exists() {
-                match fs::create_dir_all(dir) {
-                    Ok(()) => {}
-                    Err(e) => {
-                        tracing::warn!("create log_dir({:?}) {:?}", dir, e.to_string());
-                        continue;
-                    }
+            if kind == "stderr" {
+                splitter.push(&data, |line| output.stderr_tail.push(line));
+                if eof {
+                    splitter.finish(|line| output.stderr_tail.push(line));
                 }
             }
 
-            let hour = current_hour();
-            let path = dir.join(format!("{kind}.{hour}.log"));
-            let need_rotate = hour != active_hour;
-            active_hour = hour;
+            let written = 'write: {
+                if data.is_empty() || !target.to_file() || cfg.output_dir.is_empty() {
+                    break 'write LogWrite::Skipped;
+                }
+
+                // 读到数据时立即取号，即使这次写入文件失败，下一块数据的行首判断也不会错乱
+                let prefixed = match seq_prefixer.as_mut() {
+                    Some(p) => Cow::Owned(p.apply(&data)),
+                    None => Cow::Borrowed(&*data),
+                };
+
+                // 上次失败后等待一段时间再重试，期间的输出直接转到 procd 的日志
+                if retry_at.is_some_and(|t| Instant::now() < t) {
+                    break 'write LogWrite::Suspended;
+                }
+
+                let dir = Path::new(&cfg.output_dir);
+                if !dir.exists()
+                    && let Err(e) = create_log_dir(dir, cfg.log_mode_perms)
+                {
+                    break 'write LogWrite::Failed(format!("create {:?}: {}", dir, e));
+                }
+
+                let hour = current_hour(cfg.log_timezone);
+                let path = dir.join(format!("{kind}.{hour}.log"));
+                let need_rotate = hour != active_hour;
+                active_hour = hour;
+
+                let missing = fs::metadata(&path).is_err();
 
-            let missing = fs::metadata(&path).is_err();
+                if missing || need_rotate || file.is_none() {
+                    match open_log_file(&path, false, cfg.log_mode_perms) {
+                        Ok(f) => {
+                            file = Some(f);
+                            output.log_files.set(kind, &path);
+                            tracing::info!("open_log {:?}", &path);
+                        }
+                        Err(e) => {
+                            file = None;
+                            break 'write LogWrite::Failed(format!("open {:?}: {}", path, e));
+                        }
+                    };
+                }
+
+                let Some(f) = file.as_mut() else {
+                    break 'write LogWrite::Skipped;
+                };
+                if let Err(e) = f.write_all(&prefixed).and_then(|_| f.flush()) {
+                    file = None;
+                    break 'write LogWrite::Failed(format!("write {:?}: {}", path, e));
+                }
+                unsynced = true;
+                LogWrite::Written
+            };
 
-            if missing || need_rotate || file.is_none() {
-                match OpenOptions::new().create(true).append(true).open(Path::new(&path)) {
-                    Ok(f) => {
-                        file = Some(f);
-                        tracing::info!("open_log {:?}", &path);
+            // 无法写入文件时只提示一次并标记 log_writable=false，之后定期重试；
+            // 期间没有输出到日志的数据转到 procd 的日志，不会丢失
+            match written {
+                LogWrite::Skipped => {}
+                LogWrite::Written => {
+                    if retry_at.take().is_some() {
+                        tracing::warn!("output_dir {:?} is writable again", cfg.output_dir);
+                        output.log_writable.set(kind, true);
                     }
-                    Err(e) => {
-                        tracing::warn!("open_log failed {:?}", e);
+                }
+                LogWrite::Failed(_) | LogWrite::Suspended => {
+                    if let LogWrite::Failed(e) = written {
+                        if retry_at.is_none() {
+                            tracing::error!(
+                                "failed to write {} log to output_dir {:?}: {}; writing output to procd log until it recovers",
+                                kind,
+                                cfg.output_dir,
+                                e
+                            );
+                            output.log_writable.set(kind, false);
+                        }
+                        retry_at = Some(Instant::now() + LOG_RETRY_INTERVAL);
                     }
-                };
+                    if !target.to_log() {
+                        emit(
+                            output.level.get(),
+                            kind,
+                            pid,
+                            &cfg.name,
+                            &String::from_utf8_lossy(&data),
+                        );
+                    }
+                }
             }
 
-            if let Some(f) = file.as_mut() {
-                if let Err(e) = f.write_all(&buf[..n]) {
-                    tracing::warn!("write log failed: {:?}", e);
-                    file = None
+            if eof {
+                if let Some(f) = file.as_ref()
+                    && let Err(e) = f.sync_data()
+                {
+                    tracing::warn!("sync log failed: {:?}", e);
                 }
+                break;
             }
         }
     });
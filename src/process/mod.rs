@@ -1,3 +1,12 @@
+pub mod health;
+pub mod hook;
 pub mod logger;
 pub mod registry;
+pub mod reload;
+#[cfg(all(target_os = "linux", feature = "seccomp"))]
+pub mod seccomp;
+pub mod shutdown;
+#[cfg(unix)]
+pub mod socket;
+pub mod stats;
 pub mod supervisor;
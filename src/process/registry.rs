@@ -1,30 +1,64 @@
 use chrono::{DateTime, Local};
 use serde::Serialize;
 use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
-use std::time::Duration;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
-use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use std::time::{Duration, Instant};
+use std::{collections::HashMap, sync::Arc, sync::RwLock};
 use tokio::sync::mpsc;
 
-use crate::config::ProcessConfig;
+use crate::config::{ProcessConfig, redact_env};
+use crate::process::logger::{OutputState, TailLines};
 
 #[derive(Serialize, Clone, Debug, PartialEq)]
 
 pub enum ProcState {
     Ready,    // 就绪
+    Starting, // 已启动，等待 ready_file 出现
     Running,  // 正常运行
-    Stopping, // 即将停止，收到 Kill 和 Restart 命令了
+    Stopping, // 正在停止，已发送 SIGTERM/SIGKILL，等待进程退出
 
     Error(String), // 启动失败
     Stopped,       // 停止
     Killed,        // 被手动(使用 API)杀死了
-    Exited(i32),   // 程序自己退出
+    // 程序自己退出，正常退出时有 code，被信号杀死时有 signal（仅 Unix）
+    Exited { code: Option<i32>, signal: Option<i32> },
+}
+
+impl std::fmt::Display for ProcState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcState::Error(msg) => write!(f, "Error({})", msg),
+            ProcState::Exited { signal: Some(sig), .. } => write!(f, "Exited(signal {})", sig),
+            ProcState::Exited { code: Some(code), .. } => write!(f, "Exited({})", code),
+            ProcState::Exited { .. } => write!(f, "Exited"),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+// 健康检查的结果
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub enum Health {
+    Healthy,   // 检查通过
+    Degraded,  // 检查失败，但未达到失败次数上限
+    Unhealthy, // 连续失败达到上限，即将重启
+}
+
+// exec 健康检查最近一次的结果
+#[derive(Serialize, Clone, Debug)]
+pub struct HealthCmdResult {
+    pub time: String,           // 检查时间
+    pub exit_code: Option<i32>, // 退出码，超时、无法运行或被信号杀死时为空
+    pub output: String,         // stdout 和 stderr 的末尾部分，无法运行时为错误信息
 }
 
 pub enum ControlMsg {
-    Kill,    // 杀死进程，后续不会继续运行
-    Restart, // 重启进程
+    Kill,                  // 杀死进程，后续不会继续运行
+    Restart(&'static str), // 重启进程，参数为重启原因，记录到 stop_reason
+    BlueGreen,             // 先启动新实例，就绪后再停止旧实例
 }
 
 #[derive(Clone)]
@@ -35,20 +69,70 @@ pub struct ProcessEntry {
     pub cmd_abs_path: Option<String>, //命令的绝对地址
     pub pid: Option<u32>,
     pub control_tx: mpsc::Sender<ControlMsg>,
-    pub start_time: Option<DateTime<Local>>, // 进程启动时间
-    pub start_count: u64,                    // 程序启动次数
-    pub exit_time: Option<DateTime<Local>>,  // 进程上次退出时间
-    pub last_modified: Option<SystemTime>,   // cmd 文件启动时的修改时间
+    pub start_time: Option<DateTime<Local>>,  // 进程启动时间
+    pub start_count: u64,                     // 程序启动次数
+    pub exit_time: Option<DateTime<Local>>,   // 进程上次退出时间
+    pub next_run: Option<DateTime<Local>>,    // 配置了 next 时，退出后等待的下次启动时间，再次启动时清空
+    pub last_modified: Option<SystemTime>,    // cmd 文件启动时的修改时间
+    pub envs: Vec<String>,                    // 启动时实际使用的环境变量（敏感值已脱敏）
+    pub last_reload: Option<DateTime<Local>>, // 上次 reload(SIGHUP) 的时间
+    pub total_runtime_secs: u64,              // 历次运行的累计时长，进程退出时更新
+    pub health: Option<Health>,               // 健康检查结果，未配置或未检查时为 None
+    pub output: OutputState,                  // 子进程输出相关的共享状态
+    pub restart_times: VecDeque<Instant>,     // 最近自动重启的时间，只保留 restart_window 内的
+    pub last_heartbeat: Option<Instant>,      // supervise 循环最近一次心跳的时间
+    pub supervisor_healthy: bool,             // supervise 循环是否正常，心跳超时时由 watchdog 置为 false
+    pub stop_reason: Option<String>,          // 上次停止的原因，如 exited、restart、kill、max_run、memory
+    pub restarts_since_success: u64,          // 上次稳定运行（持续 min_uptime）以来自动重启的次数
+    pub pending_since: Option<Instant>,       // 本次进入 Starting/Ready/Error 的时间，处于其他状态时为空
+    pub pending: Duration,                    // 上次 Running 以来，之前各段 Starting/Ready/Error 的累计时长
+    pub stuck: bool,                          // supervise 循环在运行，但长时间没有进入 Running，由 watchdog 更新
+    pub health_cmd: Option<HealthCmdResult>,  // exec 健康检查最近一次的结果，进程重启后保留
 }
 
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5); // supervise 循环更新心跳的间隔
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(30); // 超过该时长没有心跳，认为 supervise 循环卡住
+const STUCK_TIMEOUT: Duration = Duration::from_secs(60); // 超过该时长（且超过 ready_timeout）仍未进入 Running，认为进程启动卡住
+
 pub struct Registry {
     start: DateTime<Local>,
-    inner: Arc<Mutex<HashMap<String, ProcessEntry>>>,
+    // 读多写少：list/find/get_control 使用读锁，互不阻塞
+    // 不使用 DashMap，因为 register_process 需要在同一把写锁内根据 len() 分配 index
+    inner: Arc<RwLock<HashMap<String, ProcessEntry>>>,
+    memory_used: AtomicU64, // 所有运行中进程（含子进程）的内存总和，单位字节，由内存采样任务定期更新
+    state_version: tokio::sync::watch::Sender<u64>, // 任意进程的状态变化时加 1，等待状态的一方订阅后重新检查
+}
+
+// wait_running 的结果，各列表为进程名称
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct WaitResult {
+    pub ok: bool,             // 全部进程都已进入 Running
+    pub reached: Vec<String>, // 已进入 Running
+    pub pending: Vec<String>, // 超时时仍未进入 Running
+    pub waited_ms: u64,       // 实际等待的毫秒数
+}
+
+// 简要的运行状态，用于外部监控轮询，只读取 registry，不刷新进程信息
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct StatusSummary {
+    pub ok: bool, // 没有出错、不健康和启动卡住的进程
+    pub total: usize,
+    pub running: usize,   // Running
+    pub starting: usize,  // Starting、Stopping
+    pub stopped: usize,   // Ready、Stopped、Killed、Exited
+    pub error: usize,     // Error
+    pub unhealthy: usize, // 健康检查为 Degraded 或 Unhealthy
+    pub stuck: usize,     // 长时间没有进入 Running 的进程
+    pub memory_used: u64, // 最近一次采样的内存总和，单位字节
+    pub uptime_secs: u64, // procd 已运行的秒数
 }
 
 #[derive(Serialize, Clone, Debug)]
 pub struct ProcessOut {
     pub name: String,
+    pub project: Option<String>,          // 所属项目，用于分组展示
+    pub description: String,              // 进程的说明
+    pub labels: BTreeMap<String, String>, // 自定义标签
     pub cmd: ProcessConfig,
     pub cmd_abs: String,
     pub state: ProcState,
@@ -56,37 +140,235 @@ pub struct ProcessOut {
     pub start_time: Option<String>,
     pub start_count: u64,
     pub exit_time: Option<String>,
+    pub next_run: Option<String>, // 配置了 next 的定时任务下次运行的时间，只在退出后等待期间有值
     pub memory_limit: u32,
     pub memory_used: String,
+    pub cpu_usage: f32,   // 进程及其子进程的 CPU 使用率，由 API 填充
+    pub uptime_secs: u64, // 本次运行已持续的秒数
     pub web_address: String,
-    pub sandbox: bool,         // 使用启用沙盒
-    pub mtime: Option<String>, // cmd 文件的最后修改时间
-    pub child_pids: Vec<u32>,  // 子进程的 pid 列表
+    pub sandbox: bool,             // 使用启用沙盒
+    pub mtime: Option<String>,     // cmd 文件的最后修改时间
+    pub stale: bool,               // cmd 文件在启动后被修改，需要重启
+    pub child_pids: Vec<u32>,      // 子进程的 pid 列表
+    pub listen_ports: Vec<u16>,    // 进程及其子进程正在监听的 TCP 端口，仅 Linux，由 API 填充
+    pub run_user: Option<String>,  // 进程实际生效的用户
+    pub run_group: Option<String>, // 进程实际生效的用户组
+    pub last_reload: Option<String>,
+    pub total_runtime_secs: u64, // 累计运行时长，包含当前这次运行
+    pub health: Option<Health>,
+    pub restarts_in_window: usize,           // restart_window 内自动重启的次数
+    pub supervisor_healthy: bool,            // 管理该进程的 supervise 循环是否正常
+    pub stop_reason: Option<String>,         // 上次停止的原因
+    pub log_level: String,                   // 子进程输出当前的日志级别
+    pub log_lines_total: u64,                // 累计输出的行数
+    pub log_rate: f64,                       // 最近每秒输出的行数
+    pub restarts_since_success: u64,         // 上次稳定运行以来自动重启的次数，持续增长说明正在崩溃循环
+    pub current_log_out: Option<String>,     // 当前写入的 stdout 日志文件的绝对路径，未写入文件时为空
+    pub current_log_err: Option<String>,     // 当前写入的 stderr 日志文件的绝对路径
+    pub stuck: bool,                         // 一直没能进入 Running，如反复启动失败、ready_file 迟迟不出现
+    pub stuck_secs: u64,                     // 未进入 Running 已持续的秒数，stuck 为 false 时为 0
+    pub health_cmd: Option<HealthCmdResult>, // exec 健康检查最近一次的结果
+    pub log_writable: bool, // 日志文件是否可写，output_dir 无法创建或写入时为 false，输出改为写入 procd 的日志
+}
+
+// 单个进程的详情
+#[derive(Serialize, Clone, Debug)]
+pub struct ProcessDetail {
+    #[serde(flatten)]
+    pub process: ProcessOut,
+    pub envs: Vec<String>,
+    pub stderr_tail: Vec<String>, // 最近的 stderr 输出
+}
+
+fn file_mtime(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| {
+            tracing::warn!("read metadata({}) failed: {:?}", path, e);
+        })
+        .ok()
+}
+
+// 运行中的进程，其 cmd 文件在启动后被修改过，需要重启才能使用新的程序
+fn cmd_changed(state: &ProcState, cmd_abs_path: Option<&str>, last_modified: Option<SystemTime>) -> bool {
+    match cmd_abs_path {
+        Some(path) => *state == ProcState::Running && file_mtime(path) != last_modified,
+        None => false,
+    }
 }
 
 impl ProcessEntry {
-    fn get_cmd_mtime(&self) -> Option<std::time::SystemTime> {
-        if self.cmd_abs_path.is_none() {
-            return None;
+    fn get_cmd_mtime(&self) -> Option<SystemTime> {
+        file_mtime(self.cmd_abs_path.as_deref()?)
+    }
+
+    fn is_stale(&self) -> bool {
+        cmd_changed(&self.state, self.cmd_abs_path.as_deref(), self.last_modified)
+    }
+
+    // 上次 Running 以来处于 Starting/Ready/Error（启动失败或等待就绪）的累计时长
+    // Exited、Stopped 等状态不计时，如按 next 等待下次运行的定时任务
+    fn pending_time(&self) -> Duration {
+        self.pending + self.pending_since.map_or(Duration::ZERO, |t| t.elapsed())
+    }
+
+    // 是否启动卡住：supervise 循环在运行，但启动失败或等待就绪的时间超过 STUCK_TIMEOUT 和 ready_timeout
+    fn is_stuck(&self) -> bool {
+        let timeout = match self.cmd.ready_file {
+            Some(_) => STUCK_TIMEOUT.max(self.cmd.ready_timeout),
+            None => STUCK_TIMEOUT,
+        };
+        !self.control_tx.is_closed() && self.pending_time() > timeout
+    }
+
+    // 当前这次运行已持续的秒数，进程未运行时为 0
+    fn current_runtime_secs(&self) -> u64 {
+        match (&self.state, self.start_time) {
+            (ProcState::Starting | ProcState::Running | ProcState::Stopping, Some(start)) => {
+                (Local::now() - start).num_seconds().max(0) as u64
+            }
+            _ => 0,
+        }
+    }
+
+    // 进入 Running 时清空计时；进入 Starting/Ready/Error 时开始计时，进入其他状态时暂停，累计到 pending
+    fn update_pending(&mut self) {
+        match self.state {
+            ProcState::Running => {
+                self.pending_since = None;
+                self.pending = Duration::ZERO;
+                self.stuck = false;
+            }
+            ProcState::Starting | ProcState::Ready | ProcState::Error(_) => {
+                self.pending_since.get_or_insert_with(Instant::now);
+            }
+            _ => {
+                if let Some(t) = self.pending_since.take() {
+                    self.pending += t.elapsed();
+                }
+            }
         }
+    }
+
+    fn to_out(&self, name: &str) -> ProcessOut {
+        let mut out = self.to_out_without_stale(name);
+        out.stale = self.is_stale();
+        out
+    }
+
+    // 不读取文件，可以在持有锁时调用，stale 由调用方在释放锁后计算
+    fn to_out_without_stale(&self, name: &str) -> ProcessOut {
+        let start_time_str = self.start_time.map(|t| t.format(TIME_FMT).to_string());
+        let exit_time_str = self.exit_time.map(|t| t.format(TIME_FMT).to_string());
+        let mtime_str: Option<String> = self.last_modified.map(|t| {
+            let dt: DateTime<Local> = t.into();
+            dt.format(TIME_FMT).to_string()
+        });
+        let mut cmd = self.cmd.clone();
+        cmd.envs = cmd.envs.iter().map(|e| redact_env(e)).collect();
 
-        let path = self.cmd_abs_path.clone().unwrap();
-        std::fs::metadata(&path)
-            .and_then(|m| m.modified())
-            .map_err(|e| {
-                tracing::warn!("read metadata({}) failed: {:?}", &path, e);
-            })
-            .ok()
+        ProcessOut {
+            name: name.to_string(),
+            project: self.cmd.project.clone(),
+            description: self.cmd.description.clone(),
+            labels: self.cmd.labels.clone(),
+            state: self.state.clone(),
+            cmd,
+            cmd_abs: self.cmd_abs_path.clone().unwrap_or("".to_string()),
+            pid: self.pid.unwrap_or(0),
+            start_time: start_time_str,
+            start_count: self.start_count,
+            exit_time: exit_time_str,
+            next_run: self.next_run.map(|t| t.format(TIME_FMT).to_string()),
+            memory_limit: self.cmd.memory_limit.unwrap_or(0),
+            memory_used: "".to_string(),
+            cpu_usage: 0.0,
+            uptime_secs: self.current_runtime_secs(),
+            web_address: self.cmd.web_address.clone(),
+            sandbox: !self.cmd.sandbox.is_empty(),
+            mtime: mtime_str,
+            stale: false,
+            child_pids: vec![],
+            listen_ports: vec![],
+            run_user: None,
+            run_group: None,
+            last_reload: self.last_reload.map(|t| t.format(TIME_FMT).to_string()),
+            total_runtime_secs: self.total_runtime_secs + self.current_runtime_secs(),
+            health: self.health.clone(),
+            restarts_in_window: self
+                .restart_times
+                .iter()
+                .filter(|t| t.elapsed() <= self.cmd.restart_window)
+                .count(),
+            supervisor_healthy: self.supervisor_healthy,
+            stop_reason: self.stop_reason.clone(),
+            log_level: self.output.level.get().to_string(),
+            log_lines_total: self.output.line_stats.total(),
+            log_rate: (self.output.line_stats.rate() * 100.0).round() / 100.0,
+            restarts_since_success: self.restarts_since_success,
+            current_log_out: self.output.log_files.stdout(),
+            current_log_err: self.output.log_files.stderr(),
+            stuck: self.stuck,
+            stuck_secs: match self.stuck {
+                true => self.pending_time().as_secs(),
+                false => 0,
+            },
+            health_cmd: self.health_cmd.clone(),
+            log_writable: self.output.log_writable.get(),
+        }
     }
 }
 
 const TIME_FMT: &str = "%Y-%m-%d %H:%M:%S";
 
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Registry {
     pub fn new() -> Self {
         Registry {
             start: Local::now(),
-            inner: Arc::new(Mutex::new(HashMap::new())),
+            inner: Arc::new(RwLock::new(HashMap::new())),
+            memory_used: AtomicU64::new(0),
+            state_version: tokio::sync::watch::Sender::new(0),
+        }
+    }
+
+    fn notify_state(&self) {
+        self.state_version.send_modify(|v| *v = v.wrapping_add(1));
+    }
+
+    // 等待 names 中的进程全部进入 Running，超时后返回当时的状态；names 中的进程需要已注册
+    pub async fn wait_running(&self, names: &[String], timeout: Duration) -> WaitResult {
+        let start = Instant::now();
+        let deadline = tokio::time::Instant::now() + timeout;
+        // 先订阅再检查，检查之后发生的变化一定会唤醒下面的 changed()
+        let mut rx = self.state_version.subscribe();
+        loop {
+            let (reached, pending): (Vec<String>, Vec<String>) = {
+                let registry = self.inner.read().unwrap();
+                names
+                    .iter()
+                    .cloned()
+                    .partition(|n| registry.get(n).is_some_and(|pe| pe.state == ProcState::Running))
+            };
+            let done = pending.is_empty();
+            if done || tokio::time::Instant::now() >= deadline {
+                return WaitResult {
+                    ok: done,
+                    reached,
+                    pending,
+                    waited_ms: start.elapsed().as_millis() as u64,
+                };
+            }
+            // sender 由 self 持有，不会关闭
+            tokio::select! {
+                _ = rx.changed() => {}
+                _ = tokio::time::sleep_until(deadline) => {}
+            }
         }
     }
 
@@ -100,9 +382,39 @@ impl Registry {
         tokio::spawn(async move {
             loop {
                 tokio::time::sleep(dur).await;
-                let names: Vec<String> = self.inner.lock().unwrap().keys().cloned().collect();
+                let names: Vec<String> = self.inner.read().unwrap().keys().cloned().collect();
                 for name in &names {
-                    self.watch_one(&name);
+                    self.watch_one(name);
+                }
+            }
+        });
+    }
+
+    // 检查运行中进程的 supervise 循环心跳，超时则标记为不正常，用于发现 supervise 自身的 bug
+    pub fn watchdog(self: Arc<Self>) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+                let mut registry = self.inner.write().unwrap();
+                for (name, entry) in registry.iter_mut() {
+                    // 只有 Starting/Running 时 supervise 循环在持续更新心跳
+                    let stale = matches!(entry.state, ProcState::Starting | ProcState::Running)
+                        && entry.last_heartbeat.is_some_and(|t| t.elapsed() > HEARTBEAT_TIMEOUT);
+                    if stale && entry.supervisor_healthy {
+                        tracing::error!("{}: no supervisor heartbeat for {:?}", name, HEARTBEAT_TIMEOUT);
+                    } else if !stale && !entry.supervisor_healthy {
+                        tracing::info!("{}: supervisor heartbeat recovered", name);
+                    }
+                    entry.supervisor_healthy = !stale;
+
+                    let stuck = entry.is_stuck();
+                    if stuck && !entry.stuck {
+                        let secs = entry.pending_time().as_secs();
+                        tracing::warn!("{}: stuck, not running for {}s ({})", name, secs, entry.state);
+                    } else if !stuck && entry.stuck {
+                        tracing::info!("{}: no longer stuck", name);
+                    }
+                    entry.stuck = stuck;
                 }
             }
         });
@@ -124,11 +436,7 @@ impl Registry {
             return;
         }
 
-        let current_mtime = pe.get_cmd_mtime();
-        if current_mtime.is_none() {
-            tracing::warn!("watch_one({}) get_current_mtime is null", name);
-        }
-        if current_mtime == pe.last_modified {
+        if !pe.is_stale() {
             return;
         }
         tracing::info!(
@@ -136,38 +444,70 @@ impl Registry {
             name,
             pe.cmd.cmd,
             pe.last_modified,
-            current_mtime
+            pe.get_cmd_mtime()
         );
-        let _ = pe.control_tx.clone().try_send(ControlMsg::Restart);
+        let _ = pe.control_tx.clone().try_send(ControlMsg::Restart("file_changed"));
     }
 
     pub fn find(&self, name: &str) -> Option<ProcessEntry> {
-        self.inner.lock().unwrap().get(name).cloned()
+        self.inner.read().unwrap().get(name).cloned()
+    }
+
+    // 只注册进程不启动（auto_start=false），丢弃接收端后 control channel 即关闭，restart/kill 会提示先启动
+    pub fn register_idle(&self, cmd: ProcessConfig) {
+        let _ = self.register_process(&cmd.name.clone(), cmd);
     }
 
-    pub fn register_process(&self, name: &str, cmd: ProcessConfig, tx: mpsc::Sender<ControlMsg>) {
-        let mut registry = self.inner.lock().unwrap();
+    // 注册进程并返回 control channel 的接收端，由 supervise 循环持有
+    // 每个进程同一时间只能有一个 supervise 循环：已有循环仍持有接收端（channel 未关闭）时返回 None
+    pub fn register_process(&self, name: &str, cmd: ProcessConfig) -> Option<mpsc::Receiver<ControlMsg>> {
+        let (tx, rx) = mpsc::channel::<ControlMsg>(8);
+        let mut registry = self.inner.write().unwrap();
         let index: i32 = registry.len() as i32;
 
         match registry.entry(name.to_string()) {
             Entry::Occupied(mut e) => {
+                if !e.get().control_tx.is_closed() {
+                    tracing::warn!("register_process {}: already supervised", name);
+                    return None;
+                }
                 e.get_mut().control_tx = tx;
+                e.get_mut().restart_times.clear(); // 手动启动后重新统计重启次数
+                e.get_mut().pending_since = Some(Instant::now());
+                e.get_mut().pending = Duration::ZERO;
+                e.get_mut().stuck = false;
                 tracing::info!("register_process_update {}", name);
             }
             Entry::Vacant(e) => {
                 let abs_path: Option<String> = cmd.cmd_abs_path().ok().map(|p| p.to_string_lossy().to_string());
 
+                let output = OutputState::new(&cmd);
                 let mut pe = ProcessEntry {
                     index: index + 1,
                     state: ProcState::Ready,
-                    cmd: cmd,
+                    cmd,
                     cmd_abs_path: abs_path,
                     pid: None,
                     control_tx: tx,
                     start_time: None,
                     start_count: 0,
                     exit_time: None,
+                    next_run: None,
                     last_modified: None,
+                    envs: vec![],
+                    last_reload: None,
+                    total_runtime_secs: 0,
+                    health: None,
+                    output,
+                    restart_times: VecDeque::new(),
+                    last_heartbeat: None,
+                    supervisor_healthy: true,
+                    stop_reason: None,
+                    restarts_since_success: 0,
+                    pending_since: Some(Instant::now()),
+                    pending: Duration::ZERO,
+                    stuck: false,
+                    health_cmd: None,
                 };
                 pe.last_modified = pe.get_cmd_mtime();
 
@@ -175,86 +515,262 @@ impl Registry {
                 tracing::info!("register_process_insert {}", name);
             }
         }
+        Some(rx)
+    }
+
+    // 所有进程当前的配置，用于 reload 时对比
+    pub fn configs(&self) -> HashMap<String, ProcessConfig> {
+        let registry = self.inner.read().unwrap();
+        registry
+            .iter()
+            .map(|(name, pe)| (name.clone(), pe.cmd.clone()))
+            .collect()
+    }
+
+    // 替换进程的配置；正在运行的 supervise 循环仍使用旧配置，需要重启后才生效
+    pub fn update_config(&self, cmd: ProcessConfig) {
+        let mut registry = self.inner.write().unwrap();
+        let Some(entry) = registry.get_mut(&cmd.name) else {
+            return;
+        };
+        if entry.cmd.log_level != cmd.log_level {
+            entry.output.level.set(cmd.output_level());
+        }
+        entry.cmd_abs_path = cmd.cmd_abs_path().ok().map(|p| p.to_string_lossy().to_string());
+        entry.cmd = cmd;
+    }
+
+    // 移除进程，调用前需确保它的 supervise 循环已经退出
+    pub fn remove(&self, name: &str) {
+        self.inner.write().unwrap().remove(name);
+        tracing::info!("remove_process {}", name);
     }
 
     pub fn get_control(&self, name: &str) -> Option<tokio::sync::mpsc::Sender<ControlMsg>> {
-        self.inner.lock().unwrap().get(name).map(|e| e.control_tx.clone())
+        self.inner.read().unwrap().get(name).map(|e| e.control_tx.clone())
     }
 
     pub fn set_state(&self, name: &str, state: ProcState) {
-        let mut registry = self.inner.lock().unwrap();
+        let mut registry = self.inner.write().unwrap();
         if let Some(entry) = registry.get_mut(name) {
+            let was_alive = matches!(
+                entry.state,
+                ProcState::Starting | ProcState::Running | ProcState::Stopping
+            );
             entry.state = state.clone();
+            entry.update_pending();
+            // 等待 next 期间只会是 Exited 或 Stopped（max_run），进入其他状态说明已经开始下次运行
+            if !matches!(state, ProcState::Exited { .. } | ProcState::Stopped) {
+                entry.next_run = None;
+            }
 
             if matches!(
                 state.clone(),
-                ProcState::Stopped | ProcState::Killed | ProcState::Exited(_) | ProcState::Error(_)
+                ProcState::Stopped | ProcState::Killed | ProcState::Exited { .. } | ProcState::Error(_)
             ) {
-                entry.exit_time = Some(Local::now());
+                let now = Local::now();
+                entry.exit_time = Some(now);
+
+                // 累计本次运行的时长
+                if was_alive && let Some(start) = entry.start_time {
+                    entry.total_runtime_secs += (now - start).num_seconds().max(0) as u64;
+                }
             }
 
-            if matches!(state.clone(), ProcState::Error(_)) {
+            // 启动失败时进程没有运行过，set_running 未计数；运行后再标记为 Error（如启动超时）不重复计数
+            if matches!(state.clone(), ProcState::Error(_)) && !was_alive {
                 entry.start_count += 1;
             }
 
             tracing::info!("set_state -> ({}, {:?}, {:?})", name, state, entry.pid.unwrap_or(0));
+            self.notify_state();
         } else {
             panic!("set_state {} not found", name);
         }
     }
 
-    pub fn set_running(&self, name: &str, pid: u32) {
-        let mut registry = self.inner.lock().unwrap();
+    // 进程已启动；配置了 ready_file 时先进入 Starting，等文件出现后再由 supervise 设置为 Running
+    pub fn set_running(&self, name: &str, pid: u32, envs: Vec<String>) {
+        let mut registry = self.inner.write().unwrap();
         if let Some(entry) = registry.get_mut(name) {
-            entry.state = ProcState::Running;
+            entry.state = match entry.cmd.ready_file {
+                Some(_) => ProcState::Starting,
+                None => ProcState::Running,
+            };
+            entry.update_pending();
+            entry.next_run = None;
             entry.pid = Some(pid);
-            tracing::info!("set_state -> ({}, {:?}, {:?})", name, ProcState::Running, pid);
+            entry.envs = envs;
+            entry.health = None; // 新启动的进程，等待首次检查
+            entry.last_heartbeat = Some(Instant::now());
+            tracing::info!("set_state -> ({}, {:?}, {:?})", name, entry.state, pid);
             entry.start_time = Some(Local::now());
             entry.start_count += 1;
 
             entry.last_modified = entry.get_cmd_mtime(); // 运行后，立即更新文件时间
+            self.notify_state();
         } else {
             panic!("set_running {} not found", name)
         }
     }
 
+    // 退出后按 next 等待时，记录下次启动的时间
+    pub fn set_next_run(&self, name: &str, next_run: DateTime<Local>) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.next_run = Some(next_run);
+        }
+    }
+
+    pub fn heartbeat(&self, name: &str) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.last_heartbeat = Some(Instant::now());
+        }
+    }
+
+    // 记录一次自动重启；restart_window 内已重启 max_restarts 次时不再记录，返回 false
+    pub fn record_restart(&self, name: &str) -> bool {
+        let mut registry = self.inner.write().unwrap();
+        let Some(entry) = registry.get_mut(name) else {
+            return false;
+        };
+        let window = entry.cmd.restart_window;
+        entry.restart_times.retain(|t| t.elapsed() <= window);
+        if let Some(max) = entry.cmd.max_restarts
+            && entry.restart_times.len() >= max as usize
+        {
+            return false;
+        }
+        entry.restart_times.push_back(Instant::now());
+        entry.restarts_since_success += 1;
+        true
+    }
+
+    // 清除自动重启的计数，restart_window 和 max_restarts 重新开始计算
+    pub fn reset_restarts(&self, name: &str) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.restart_times.clear();
+            entry.restarts_since_success = 0;
+        }
+    }
+
+    // 进程持续运行达到 min_uptime，视为启动成功，重新计数
+    pub fn mark_stable(&self, name: &str) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name)
+            && entry.restarts_since_success > 0
+        {
+            tracing::info!("up for {:?}, reset restarts_since_success", entry.cmd.min_uptime);
+            entry.restarts_since_success = 0;
+        }
+    }
+
+    pub fn set_stop_reason(&self, name: &str, reason: &str) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.stop_reason = Some(reason.to_string());
+        }
+    }
+
+    pub fn set_health(&self, name: &str, health: Option<Health>) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.health = health;
+        }
+    }
+
+    pub fn set_health_cmd(&self, name: &str, exit_code: Option<i32>, output: String) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.health_cmd = Some(HealthCmdResult {
+                time: Local::now().format(TIME_FMT).to_string(),
+                exit_code,
+                output,
+            });
+        }
+    }
+
+    pub fn set_reloaded(&self, name: &str) {
+        if let Some(entry) = self.inner.write().unwrap().get_mut(name) {
+            entry.last_reload = Some(Local::now());
+        }
+    }
+
+    // 持有锁时直接生成 ProcessOut，不复制整个 ProcessEntry；检查 cmd 文件是否变化需要读文件，放到锁外
     pub fn list(&self) -> Vec<ProcessOut> {
+        let mut items: Vec<(ProcessOut, Option<SystemTime>)> = {
+            let registry = self.inner.read().unwrap();
+            let mut entries: Vec<_> = registry.iter().collect();
+            // 先按项目分组，同一项目内按配置顺序
+            entries.sort_by(|(_, a), (_, b)| (&a.cmd.project, a.index).cmp(&(&b.cmd.project, b.index)));
+            entries
+                .into_iter()
+                .map(|(k, v)| (v.to_out_without_stale(k), v.last_modified))
+                .collect()
+        };
+
+        for (out, last_modified) in items.iter_mut() {
+            let path = (!out.cmd_abs.is_empty()).then_some(out.cmd_abs.as_str());
+            out.stale = cmd_changed(&out.state, path, *last_modified);
+        }
+        items.into_iter().map(|(out, _)| out).collect()
+    }
+
+    // list 之前的实现：持有锁时复制每个 ProcessEntry，释放锁后再生成 ProcessOut
+    // 只保留用于 benches/registry_list.rs 对比两种实现
+    #[doc(hidden)]
+    pub fn list_cloned(&self) -> Vec<ProcessOut> {
         let entries: Vec<(String, ProcessEntry)> = {
-            let registry = self.inner.lock().unwrap();
+            let registry = self.inner.read().unwrap();
             let mut ret: Vec<_> = registry.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
-            ret.sort_by_key(|(_, v)| v.index);
+            ret.sort_by(|(_, a), (_, b)| (&a.cmd.project, a.index).cmp(&(&b.cmd.project, b.index)));
             ret
         };
+        entries.into_iter().map(|(k, v)| v.to_out(&k)).collect()
+    }
 
-        entries
-            .into_iter()
-            .map(|(k, v)| {
-                let start_time_str = v.start_time.map(|t| t.format(TIME_FMT).to_string());
-                let exit_time_str = v.exit_time.map(|t| t.format(TIME_FMT).to_string());
-                let mtime_str: Option<String> = v.last_modified.map(|t| {
-                    let dt: DateTime<Local> = t.into();
-                    dt.format(TIME_FMT).to_string()
-                });
-                ProcessOut {
-                    name: k.clone(),
-                    state: v.state.clone(),
-                    cmd: v.cmd.clone(),
-                    cmd_abs: v.cmd_abs_path.unwrap_or("".to_string()),
-                    pid: v.pid.unwrap_or(0),
-                    start_time: start_time_str,
-                    start_count: v.start_count,
-                    exit_time: exit_time_str,
-                    memory_limit: v.cmd.memory_limit.unwrap_or(0),
-                    memory_used: "".to_string(),
-                    web_address: v.cmd.web_address.clone(),
-                    sandbox: !v.cmd.sandbox.is_empty(),
-                    mtime: mtime_str,
-                    child_pids: vec![],
-                }
-            })
+    pub fn detail(&self, name: &str) -> Option<ProcessDetail> {
+        self.find(name).map(|pe| ProcessDetail {
+            process: pe.to_out(name),
+            envs: pe.envs.clone(),
+            stderr_tail: pe.output.stderr_tail.lines(),
+        })
+    }
+
+    // 各进程的 (名称, log_buffer_priority, stderr_tail)，供 total_log_buffer_bytes 裁剪使用
+    pub fn stderr_tails(&self) -> Vec<(String, u8, TailLines)> {
+        self.inner
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, pe)| (name.clone(), pe.cmd.log_buffer_priority, pe.output.stderr_tail.clone()))
             .collect()
     }
 
+    pub fn set_memory_used(&self, bytes: u64) {
+        self.memory_used.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn summary(&self) -> StatusSummary {
+        let mut s = StatusSummary {
+            memory_used: self.memory_used.load(Ordering::Relaxed),
+            uptime_secs: (Local::now() - self.start).num_seconds().max(0) as u64,
+            ..Default::default()
+        };
+        for pe in self.inner.read().unwrap().values().filter(|pe| pe.cmd.enable) {
+            s.total += 1;
+            match pe.state {
+                ProcState::Running => s.running += 1,
+                ProcState::Starting | ProcState::Stopping => s.starting += 1,
+                ProcState::Error(_) => s.error += 1,
+                ProcState::Ready | ProcState::Stopped | ProcState::Killed | ProcState::Exited { .. } => s.stopped += 1,
+            }
+            if matches!(pe.health, Some(Health::Degraded | Health::Unhealthy)) {
+                s.unhealthy += 1;
+            }
+            if pe.stuck {
+                s.stuck += 1;
+            }
+        }
+        s.ok = s.error == 0 && s.unhealthy == 0 && s.stuck == 0;
+        s
+    }
+
     pub fn start_time(&self) -> String {
         self.start.format("%Y-%m-%d %H:%M:%S").to_string()
     }
@@ -0,0 +1,335 @@
+use chrono::Local;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::config::{Config, ProcessConfig, process_files};
+use crate::process::registry::Registry;
+use crate::process::supervisor::stop_supervised;
+
+const PROCESS_DIR_POLL_INTERVAL: Duration = Duration::from_secs(5); // 检查 process_dir 变化的间隔
+
+// process_dir 中配置文件的名称、修改时间和大小，任意一项变化时需要 reload；目录无法读取时为 None
+fn dir_signature(dir: &Path) -> Option<Vec<(PathBuf, Option<SystemTime>, u64)>> {
+    let files = process_files(dir).ok()?;
+    Some(
+        files
+            .into_iter()
+            .map(|f| {
+                let meta = std::fs::metadata(&f).ok();
+                let mtime = meta.as_ref().and_then(|m| m.modified().ok());
+                let len = meta.map(|m| m.len()).unwrap_or(0);
+                (f, mtime, len)
+            })
+            .collect(),
+    )
+}
+
+// 单个进程配置的变化
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigChange {
+    Unchanged, // 没有变化
+    Updated,   // 只修改了不影响运行的字段，直接更新配置
+    Restart,   // 修改了影响运行的字段，需要重启进程
+}
+
+// 去掉不影响运行的字段（展示用的字段，运行时可修改的日志级别和 stderr 行的保留优先级），剩下的字段变化时需要重启
+fn without_runtime_fields(cfg: &ProcessConfig) -> ProcessConfig {
+    let mut cfg = cfg.clone();
+    cfg.project = None;
+    cfg.description.clear();
+    cfg.labels.clear();
+    cfg.web_address.clear();
+    cfg.port = None;
+    cfg.log_level.clear();
+    cfg.log_buffer_priority = 0;
+    cfg
+}
+
+// classify 对比进程的新旧配置，判断是否需要重启
+pub fn classify(old: &ProcessConfig, new: &ProcessConfig) -> ConfigChange {
+    if old == new {
+        ConfigChange::Unchanged
+    } else if without_runtime_fields(old) == without_runtime_fields(new) {
+        ConfigChange::Updated
+    } else {
+        ConfigChange::Restart
+    }
+}
+
+// 一次 reload 的结果，各列表为进程名称
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ReloadResult {
+    pub time: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub restarted: Vec<String>,
+    pub updated: Vec<String>, // 配置已更新但没有重启：只改了不影响运行的字段，或者进程当前没有运行
+    pub unchanged: Vec<String>,
+    pub errors: Vec<String>, // 配置检查的错误，以及未能应用的进程
+}
+
+// 单个进程 reload 的结果
+#[derive(Serialize, Clone, Debug)]
+pub struct ProcessReloadResult {
+    pub time: String,
+    pub name: String,
+    pub result: &'static str, // added、unchanged、updated、restarted，旧实例未能停止或超过 max_processes 时为 failed
+    pub changed: Vec<String>, // 变化的配置字段
+    pub errors: Vec<String>,  // 该进程的配置检查错误，以及未能应用的原因
+}
+
+// reload_process 的结果
+#[derive(Debug)]
+pub enum ProcessReload {
+    NotFound,                    // 配置文件中没有该进程
+    Forbidden,                   // 新配置中的进程不在调用方的访问范围内，没有做任何修改
+    Done(ProcessReloadResult),
+}
+
+// changed_fields 返回新旧配置中值不同的字段名称，按字段名排序
+fn changed_fields(old: &ProcessConfig, new: &ProcessConfig) -> Vec<String> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return vec![];
+    };
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect()
+}
+
+pub struct Reloader {
+    config_path: PathBuf,              // 配置文件的绝对路径，启动后工作目录会改变
+    lock: tokio::sync::Mutex<()>,      // 同一时间只执行一次 reload
+    last: Mutex<Option<ReloadResult>>, // 上次 reload 的结果
+}
+
+// 新增的进程，和启动时一样处理 auto_start
+fn start_new(reg: &Arc<Registry>, cfg: &ProcessConfig) {
+    if cfg.auto_start {
+        cfg.start_spawn(reg.clone());
+    } else {
+        reg.register_idle(cfg.clone());
+    }
+}
+
+impl Reloader {
+    pub fn new(config_path: PathBuf) -> Arc<Self> {
+        Arc::new(Reloader {
+            config_path,
+            lock: tokio::sync::Mutex::new(()),
+            last: Mutex::new(None),
+        })
+    }
+
+    pub fn last(&self) -> Option<ReloadResult> {
+        self.last.lock().unwrap().clone()
+    }
+
+    // reload 重新读取配置文件，按进程对比新旧配置：新增的启动，删除的停止并移除，影响运行的字段变化时重启
+    // http、auth 等全局配置需要重启 procd 才能生效
+    pub async fn reload(&self, reg: &Arc<Registry>) -> anyhow::Result<ReloadResult> {
+        let _guard = self.lock.lock().await;
+        let mut result = ReloadResult {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            ..Default::default()
+        };
+
+        // 配置文件有误时不做任何修改，失败也记录为上次的结果
+        let cfg = match Config::from_file(&self.config_path.to_string_lossy()) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                result.errors.push(e.to_string());
+                *self.last.lock().unwrap() = Some(result);
+                return Err(e.into());
+            }
+        };
+        result.errors = cfg.validate().iter().map(|e| e.to_string()).collect();
+
+        let old = reg.configs();
+        for new in &cfg.start_order() {
+            let Some(old_cfg) = old.get(&new.name) else {
+                start_new(reg, new);
+                result.added.push(new.name.clone());
+                continue;
+            };
+            match classify(old_cfg, new) {
+                ConfigChange::Unchanged => result.unchanged.push(new.name.clone()),
+                ConfigChange::Updated => {
+                    reg.update_config(new.clone());
+                    result.updated.push(new.name.clone());
+                }
+                ConfigChange::Restart => {
+                    // 没有运行的进程（手动停止或 auto_start=false）只更新配置，下次启动时生效
+                    let supervised = reg.get_control(&new.name).is_some_and(|tx| !tx.is_closed());
+                    if !stop_supervised(reg, old_cfg, "reload").await {
+                        result
+                            .errors
+                            .push(format!("{}: old instance did not stop, not restarted", new.name));
+                        continue;
+                    }
+                    reg.update_config(new.clone());
+                    if supervised {
+                        new.start_spawn(reg.clone());
+                        result.restarted.push(new.name.clone());
+                    } else {
+                        result.updated.push(new.name.clone());
+                    }
+                }
+            }
+        }
+
+        let names: HashSet<&str> = cfg.process.iter().map(|p| p.name.as_str()).collect();
+        for (name, old_cfg) in &old {
+            if names.contains(name.as_str()) {
+                continue;
+            }
+            if stop_supervised(reg, old_cfg, "reload").await {
+                reg.remove(name);
+                result.removed.push(name.clone());
+            } else {
+                result.errors.push(format!("{}: did not stop, not removed", name));
+            }
+        }
+        result.removed.sort();
+
+        tracing::info!(
+            "config reloaded: added={:?} removed={:?} restarted={:?} updated={:?} unchanged={} errors={:?}",
+            result.added,
+            result.removed,
+            result.restarted,
+            result.updated,
+            result.unchanged.len(),
+            result.errors
+        );
+        *self.last.lock().unwrap() = Some(result.clone());
+        Ok(result)
+    }
+
+    // reload_process 重新读取配置文件，只应用其中一个进程的配置，其他进程和全局配置保持不变
+    // 删除进程需要使用完整的 reload；allowed 按新读取的配置检查访问范围，尚未注册的进程和修改了 project 的进程也能检查到
+    pub async fn reload_process(
+        &self,
+        reg: &Arc<Registry>,
+        name: &str,
+        allowed: impl Fn(&ProcessConfig) -> bool,
+    ) -> anyhow::Result<ProcessReload> {
+        let _guard = self.lock.lock().await;
+        let cfg = Config::from_file(&self.config_path.to_string_lossy())?;
+        let Some(new) = cfg.process.iter().find(|p| p.name == name) else {
+            return Ok(ProcessReload::NotFound);
+        };
+        if !allowed(new) {
+            return Ok(ProcessReload::Forbidden);
+        }
+
+        let prefix = format!("process[{}].", name);
+        let mut result = ProcessReloadResult {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            name: name.to_string(),
+            result: "unchanged",
+            changed: vec![],
+            errors: cfg
+                .validate()
+                .iter()
+                .filter(|e| e.field.starts_with(&prefix))
+                .map(|e| e.to_string())
+                .collect(),
+        };
+
+        let old = reg.configs();
+        let Some(old_cfg) = old.get(name) else {
+            // 已从配置文件删除、尚未 reload 的进程仍在 registry 中，新增前按当前数量再检查一次上限
+            if old.len() >= cfg.max_processes {
+                result.result = "failed";
+                result
+                    .errors
+                    .push(format!("max_processes {} reached, not added", cfg.max_processes));
+                return Ok(ProcessReload::Done(result));
+            }
+            start_new(reg, new);
+            result.result = "added";
+            tracing::info!("process {} added by reload", name);
+            return Ok(ProcessReload::Done(result));
+        };
+        result.changed = changed_fields(old_cfg, new);
+        match classify(old_cfg, new) {
+            ConfigChange::Unchanged => {}
+            ConfigChange::Updated => {
+                reg.update_config(new.clone());
+                result.result = "updated";
+            }
+            ConfigChange::Restart => {
+                let supervised = reg.get_control(name).is_some_and(|tx| !tx.is_closed());
+                if !stop_supervised(reg, old_cfg, "reload").await {
+                    result.result = "failed";
+                    result
+                        .errors
+                        .push("old instance did not stop, not restarted".to_string());
+                } else {
+                    reg.update_config(new.clone());
+                    if supervised {
+                        new.start_spawn(reg.clone());
+                        result.result = "restarted";
+                    } else {
+                        result.result = "updated";
+                    }
+                }
+            }
+        }
+        tracing::info!(
+            "process {} reloaded: {} changed={:?} errors={:?}",
+            name,
+            result.result,
+            result.changed,
+            result.errors
+        );
+        Ok(ProcessReload::Done(result))
+    }
+
+    // 定期检查 process_dir 中的文件，有新增、删除或修改时重新加载配置
+    pub fn watch_process_dir(self: Arc<Self>, reg: Arc<Registry>, dir: PathBuf) {
+        tracing::info!("watching process_dir {:?}", dir);
+        tokio::spawn(async move {
+            let mut last = dir_signature(&dir);
+            loop {
+                tokio::time::sleep(PROCESS_DIR_POLL_INTERVAL).await;
+                let current = dir_signature(&dir);
+                if current == last {
+                    continue;
+                }
+                last = current;
+                tracing::info!("process_dir {:?} changed, reloading config", dir);
+                if let Err(e) = self.reload(&reg).await {
+                    tracing::error!("reload config failed: {:#}", e);
+                }
+            }
+        });
+    }
+
+    // 收到 SIGHUP 时重新加载配置
+    #[cfg(unix)]
+    pub fn watch_sighup(self: Arc<Self>, reg: Arc<Registry>) {
+        use tokio::signal::unix::{SignalKind, signal};
+        tokio::spawn(async move {
+            let mut hup = match signal(SignalKind::hangup()) {
+                Ok(s) => s,
+                Err(e) => {
+                    tracing::warn!("failed to listen for SIGHUP: {:?}", e);
+                    return;
+                }
+            };
+            while hup.recv().await.is_some() {
+                tracing::info!("received SIGHUP, reloading config");
+                if let Err(e) = self.reload(&reg).await {
+                    tracing::error!("reload config failed: {:#}", e);
+                }
+            }
+        });
+    }
+}
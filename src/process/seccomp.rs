@@ -0,0 +1,72 @@
+use seccompiler::{BpfProgram, TargetArch};
+use serde::Deserialize;
+
+// seccomp_profile 文件的格式，如：
+// default: allow
+// syscalls: ["ptrace", "mount"]
+#[derive(Deserialize, Debug)]
+struct SeccompProfile {
+    default: SeccompDefault, // 不在 syscalls 中的系统调用的处理方式
+
+    #[serde(default)]
+    syscalls: Vec<String>, // 例外的系统调用：default 为 allow 时禁止这些，为 deny 时只允许这些
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum SeccompDefault {
+    Allow,
+    Deny,
+}
+
+// 被禁止的系统调用返回 EPERM，而不是直接杀死进程，便于从进程的日志中定位问题
+const DENY_ACTION: &str = r#"{"errno": 1}"#;
+
+// load_profile 读取 seccomp_profile 文件（yaml 或 json）并编译成 BPF 程序
+pub fn load_profile(path: &str) -> anyhow::Result<BpfProgram> {
+    let profile: SeccompProfile = config::Config::builder()
+        .add_source(config::File::with_name(path))
+        .build()
+        .and_then(|c| c.try_deserialize())
+        .map_err(|e| anyhow::anyhow!("failed to load seccomp_profile {}: {}", path, e))?;
+
+    if let Some(name) = profile
+        .syscalls
+        .iter()
+        .find(|s| s.is_empty() || !s.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'))
+    {
+        anyhow::bail!("seccomp_profile {}: invalid syscall name {:?}", path, name);
+    }
+
+    // 转换成 seccompiler 的 json 格式，syscall 名称到系统调用号的映射由 seccompiler 完成
+    let (mismatch_action, match_action) = match profile.default {
+        SeccompDefault::Allow => (r#""allow""#, DENY_ACTION),
+        SeccompDefault::Deny => (DENY_ACTION, r#""allow""#),
+    };
+    let rules: Vec<String> = profile
+        .syscalls
+        .iter()
+        .map(|s| format!(r#"{{"syscall": "{}"}}"#, s))
+        .collect();
+    let json = format!(
+        r#"{{"main": {{"mismatch_action": {}, "match_action": {}, "filter": [{}]}}}}"#,
+        mismatch_action,
+        match_action,
+        rules.join(", ")
+    );
+
+    let arch = TargetArch::try_from(std::env::consts::ARCH)?;
+    let mut filters = seccompiler::compile_from_json(json.as_bytes(), arch)
+        .map_err(|e| anyhow::anyhow!("seccomp_profile {}: {}", path, e))?;
+    filters
+        .remove("main")
+        .ok_or_else(|| anyhow::anyhow!("seccomp_profile {}: no filter compiled", path))
+}
+
+// apply 在 pre_exec 中调用，为即将 exec 的子进程加载过滤规则
+pub fn apply(filter: &BpfProgram) -> std::io::Result<()> {
+    seccompiler::apply_filter(filter).map_err(|e| match e {
+        seccompiler::Error::Prctl(e) | seccompiler::Error::Seccomp(e) => e,
+        e => std::io::Error::other(e),
+    })
+}
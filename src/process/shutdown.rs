@@ -0,0 +1,66 @@
+use futures_util::future::join_all;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::{Config, ProcessConfig, ShutdownOrder};
+use crate::process::hook::run_shutdown_hook;
+use crate::process::registry::Registry;
+use crate::process::supervisor::stop_supervised;
+
+// 等待退出信号，返回信号名称
+pub async fn wait_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = match signal(SignalKind::terminate()) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!("failed to listen for SIGTERM: {:?}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return "SIGINT";
+            }
+        };
+        tokio::select! {
+            _ = term.recv() => "SIGTERM",
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "SIGINT"
+    }
+}
+
+// 同时停止一组进程（按各自的 term_timeout），全部停止或超时后返回
+async fn stop_all(reg: &Arc<Registry>, configs: &[&ProcessConfig]) {
+    let stops = configs.iter().map(|pc| async move {
+        if !stop_supervised(reg, pc, "shutdown").await {
+            tracing::warn!("{}: did not stop in time", pc.name);
+        }
+    });
+    join_all(stops).await;
+}
+
+// shutdown 按 shutdown_order 停止全部进程，之后运行 on_shutdown
+// priority 顺序下依赖其他进程的服务（start_priority 较小、后启动）先停止，不会因为依赖先退出而报错
+pub async fn shutdown(reg: &Arc<Registry>, cfg: &Config, signal: &str) {
+    tracing::info!("received {}, stopping all processes", signal);
+    let configs = reg.configs();
+    match cfg.shutdown_order {
+        ShutdownOrder::Parallel => stop_all(reg, &configs.values().collect::<Vec<_>>()).await,
+        ShutdownOrder::Priority => {
+            let mut groups: BTreeMap<i32, Vec<&ProcessConfig>> = BTreeMap::new();
+            for pc in configs.values() {
+                groups.entry(pc.start_priority).or_default().push(pc);
+            }
+            for (priority, group) in groups {
+                tracing::info!("stopping processes with start_priority {}", priority);
+                stop_all(reg, &group).await;
+            }
+        }
+    }
+    tracing::info!("all processes stopped");
+
+    run_shutdown_hook(cfg, signal).await;
+}
@@ -0,0 +1,160 @@
+use anyhow::Context;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CString, OsString};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+pub const SD_LISTEN_FDS_START: RawFd = 3; // systemd socket activation 约定的第一个 fd
+
+// procd 打开的监听 socket，按地址缓存；子进程重启时复用同一个 socket，期间的连接在 backlog 中排队
+static LISTENERS: LazyLock<Mutex<HashMap<String, OwnedFd>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// socket 地址：unix:/path 或以 / 开头的路径为 unix socket，其余为 TCP 地址（可带 tcp: 前缀）
+pub enum SocketAddr<'a> {
+    Tcp(&'a str),
+    Unix(&'a Path),
+}
+
+pub fn parse(addr: &str) -> SocketAddr<'_> {
+    if let Some(path) = addr.strip_prefix("unix:") {
+        SocketAddr::Unix(Path::new(path))
+    } else if addr.starts_with('/') {
+        SocketAddr::Unix(Path::new(addr))
+    } else {
+        SocketAddr::Tcp(addr.strip_prefix("tcp:").unwrap_or(addr))
+    }
+}
+
+// listen_fd 返回地址对应的监听 socket，第一次调用时创建；fd 带 CLOEXEC，由 ActivatedExec 放到 fd 3
+pub fn listen_fd(addr: &str) -> anyhow::Result<RawFd> {
+    let mut listeners = LISTENERS.lock().unwrap();
+    if let Some(fd) = listeners.get(addr) {
+        return Ok(fd.as_raw_fd());
+    }
+    let fd: OwnedFd = match parse(addr) {
+        SocketAddr::Tcp(a) => std::net::TcpListener::bind(a)
+            .with_context(|| format!("failed to listen on {}", a))?
+            .into(),
+        SocketAddr::Unix(path) => {
+            // 上次运行留下的 socket 文件会导致 bind 失败，只删除 socket 类型的文件
+            if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                std::fs::remove_file(path)?;
+            }
+            std::os::unix::net::UnixListener::bind(path)
+                .with_context(|| format!("failed to listen on {:?}", path))?
+                .into()
+        }
+    };
+    tracing::info!("listening on {} for socket activation", addr);
+    let raw = fd.as_raw_fd();
+    listeners.insert(addr.to_string(), fd);
+    Ok(raw)
+}
+
+// 子进程通过 socket activation 启动时的 exec 参数，都在 fork 前准备好
+// std 在 pre_exec 之后才把 Command 的环境变量设置为 environ，而 LISTEN_PID 要在 fork 后才能确定，
+// 因此这里由 pre_exec 自己调用 execvp，环境变量使用自己准备的数组，fork 后只填写 pid
+pub struct ActivatedExec {
+    fd: RawFd,
+    program: CString,
+    _args: Vec<CString>,
+    _envs: Vec<CString>,
+    listen_pid: Vec<u8>, // "LISTEN_PID=" 加上 10 位 pid 的空间和结尾的 NUL
+    argv: Vec<*const libc::c_char>,
+    envp: Vec<*const libc::c_char>,
+}
+
+// 指针指向结构体自己持有的 CString，只在子进程中使用
+unsafe impl Send for ActivatedExec {}
+unsafe impl Sync for ActivatedExec {}
+
+unsafe extern "C" {
+    static mut environ: *const *const libc::c_char;
+}
+
+impl ActivatedExec {
+    // envs 为子进程完整的环境变量，其中的 LISTEN_PID 会被替换；arg0 与 Command::arg0 相同
+    pub fn new(
+        fd: RawFd,
+        cmd: &std::process::Command,
+        arg0: Option<&str>,
+        envs: &BTreeMap<OsString, OsString>,
+    ) -> anyhow::Result<Self> {
+        let program = CString::new(cmd.get_program().as_bytes())?;
+        let mut args = vec![match arg0 {
+            Some(arg0) => CString::new(arg0)?,
+            None => program.clone(),
+        }];
+        for arg in cmd.get_args() {
+            args.push(CString::new(arg.as_bytes())?);
+        }
+        let mut env_strings = vec![];
+        for (k, v) in envs {
+            if k == "LISTEN_PID" {
+                continue;
+            }
+            let mut kv = k.as_bytes().to_vec();
+            kv.push(b'=');
+            kv.extend_from_slice(v.as_bytes());
+            env_strings.push(CString::new(kv)?);
+        }
+        let mut listen_pid = b"LISTEN_PID=".to_vec();
+        listen_pid.resize(listen_pid.len() + 11, 0);
+
+        let argv = args.iter().map(|a| a.as_ptr()).chain([std::ptr::null()]).collect();
+        let mut envp: Vec<*const libc::c_char> = env_strings.iter().map(|e| e.as_ptr()).collect();
+        envp.push(listen_pid.as_ptr() as *const libc::c_char);
+        envp.push(std::ptr::null());
+        Ok(ActivatedExec {
+            fd,
+            program,
+            _args: args,
+            _envs: env_strings,
+            listen_pid,
+            argv,
+            envp,
+        })
+    }
+
+    // 在 pre_exec 的最后调用：把监听 socket 放到 fd 3 并去掉 CLOEXEC，填写 LISTEN_PID 后 exec
+    // fork 之后只能使用 async-signal-safe 的系统调用，不能分配内存；成功时不会返回
+    pub fn exec(&mut self) -> std::io::Error {
+        let ret = unsafe {
+            if self.fd == SD_LISTEN_FDS_START {
+                libc::fcntl(self.fd, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(self.fd, SD_LISTEN_FDS_START)
+            }
+        };
+        if ret < 0 {
+            return std::io::Error::last_os_error();
+        }
+
+        let mut pid = unsafe { libc::getpid() } as u32;
+        let mut digits = [0u8; 10];
+        let mut n = 0;
+        loop {
+            digits[n] = b'0' + (pid % 10) as u8;
+            n += 1;
+            pid /= 10;
+            if pid == 0 {
+                break;
+            }
+        }
+        let value = &mut self.listen_pid[b"LISTEN_PID=".len()..];
+        for (dst, d) in value.iter_mut().zip(digits[..n].iter().rev()) {
+            *dst = *d;
+        }
+        value[n] = 0;
+
+        // 和 std 一样设置 environ 后调用 execvp，PATH 按子进程的环境变量查找
+        unsafe {
+            environ = self.envp.as_ptr();
+            libc::execvp(self.program.as_ptr(), self.argv.as_ptr());
+        }
+        std::io::Error::last_os_error()
+    }
+}
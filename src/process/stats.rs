@@ -0,0 +1,385 @@
+use chrono::Local;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio::time::Duration;
+
+use crate::config::MemoryAction;
+use crate::process::registry::{ControlMsg, ProcState, Registry};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10); // 内存检查的间隔
+const SELF_SAMPLE_INTERVAL: Duration = Duration::from_secs(60); // procd 自身资源使用的采样间隔
+const SELF_SAMPLES_MAX: usize = 7 * 24 * 60; // 最多保留的自身采样数，按 1 分钟一次约 7 天
+
+// procd 自身的 pid、内存（字节）和 CPU 使用率，调用方需要先刷新 sys 中的进程信息
+pub fn self_usage(sys: &System) -> Option<(u32, u64, f32)> {
+    let proc = sys.process(sysinfo::get_current_pid().ok()?)?;
+    Some((proc.pid().as_u32(), proc.memory(), proc.cpu_usage()))
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SelfSample {
+    pub time: String,
+    pub memory: u64, // 字节
+    pub cpu_usage: f32,
+}
+
+// procd 自身资源使用的历史，用于发现 procd 长期运行时的泄漏，只保留最近 SELF_SAMPLES_MAX 个采样
+#[derive(Default)]
+pub struct SelfMetrics {
+    samples: Mutex<VecDeque<SelfSample>>,
+}
+
+impl SelfMetrics {
+    fn push(&self, sample: SelfSample) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == SELF_SAMPLES_MAX {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> Vec<SelfSample> {
+        self.samples.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn interval_secs(&self) -> u64 {
+        SELF_SAMPLE_INTERVAL.as_secs()
+    }
+}
+
+// 定期采样 procd 自身的内存和 CPU；CPU 使用率是两次刷新之间的平均值，所以使用单独的 System 并只刷新自身
+pub fn spawn_self_sampler(metrics: Arc<SelfMetrics>) {
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        tracing::warn!("failed to get current pid, self metrics disabled");
+        return;
+    };
+    tokio::spawn(async move {
+        let mut sys = System::new();
+        let refresh = |sys: &mut System| {
+            sys.refresh_processes_specifics(
+                ProcessesToUpdate::Some(&[pid]),
+                false,
+                ProcessRefreshKind::nothing().with_memory().with_cpu(),
+            );
+        };
+        refresh(&mut sys);
+        loop {
+            tokio::time::sleep(SELF_SAMPLE_INTERVAL).await;
+            refresh(&mut sys);
+            if let Some((_, memory, cpu_usage)) = self_usage(&sys) {
+                metrics.push(SelfSample {
+                    time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                    memory,
+                    cpu_usage,
+                });
+            }
+        }
+    });
+}
+
+/// 判断 PID 是否是进程组 leader（即排除线程）
+pub fn is_real_process(pid: Pid) -> bool {
+    // 读取 /proc/[pid]/status 的 Tgid 字段
+    let path = format!("/proc/{}/status", pid);
+    if let Ok(content) = fs::read_to_string(path) {
+        for line in content.lines() {
+            if !line.starts_with("Tgid:") {
+                continue;
+            }
+            if let Some(tgid_str) = line.split_whitespace().nth(1)
+                && let Ok(tgid) = tgid_str.parse::<i32>()
+            {
+                return tgid == pid.as_u32() as i32;
+            }
+        }
+    }
+    // 如果无法读取，保守处理为 false
+    false
+}
+
+/// 统计子进程需要的进程信息；由 sysinfo::Process 实现，benches 和 tests 可以用模拟的进程表代替
+pub trait ProcInfo {
+    fn pid(&self) -> Pid;
+    fn parent(&self) -> Option<Pid>;
+    fn memory(&self) -> u64;
+    fn exists(&self) -> bool;
+    fn is_real(&self) -> bool; // 是进程而不是线程
+}
+
+impl ProcInfo for sysinfo::Process {
+    fn pid(&self) -> Pid {
+        self.pid()
+    }
+    fn parent(&self) -> Option<Pid> {
+        self.parent()
+    }
+    fn memory(&self) -> u64 {
+        self.memory()
+    }
+    fn exists(&self) -> bool {
+        self.exists()
+    }
+    fn is_real(&self) -> bool {
+        is_real_process(self.pid())
+    }
+}
+
+/// 按父进程建立子进程索引，每次刷新只构建一次，避免为每个被管理的进程遍历全部进程
+pub fn build_children_index<P: ProcInfo>(processes: &HashMap<Pid, P>) -> HashMap<Pid, Vec<Pid>> {
+    let mut index: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for proc in processes.values() {
+        if let Some(parent) = proc.parent() {
+            index.entry(parent).or_default().push(proc.pid());
+        }
+    }
+    index
+}
+
+/// 获取父进程 pid 的所有子进程 PID 列表和总内存（KB）
+/// 返回 (Vec<Pid>, total_memory)
+pub fn get_child_pids_and_total_memory<P: ProcInfo>(
+    processes: &HashMap<Pid, P>,
+    children: &HashMap<Pid, Vec<Pid>>,
+    parent_pid: Pid,
+) -> (Vec<Pid>, u64) {
+    let mut pids = Vec::new();
+    let mut total_memory = 0;
+
+    // 从父进程开始逐层查找子进程、孙子进程
+    let mut pending = vec![parent_pid];
+    while let Some(pid) = pending.pop() {
+        let Some(child_pids) = children.get(&pid) else {
+            continue;
+        };
+        for child in child_pids {
+            let Some(proc) = processes.get(child) else {
+                continue;
+            };
+            if !proc.exists() {
+                continue;
+            }
+            if !proc.is_real() {
+                continue;
+            }
+            pids.push(*child);
+            total_memory += proc.memory();
+            pending.push(*child);
+        }
+    }
+
+    (pids, total_memory)
+}
+
+/// 建立索引之前的实现：每一层都遍历全部进程查找子进程
+/// 只保留用于 benches/children_index.rs 和 tests/stats.rs 对比结果和性能
+#[doc(hidden)]
+pub fn get_child_pids_and_total_memory_by_scan<P: ProcInfo>(
+    processes: &HashMap<Pid, P>,
+    parent_pid: Pid,
+) -> (Vec<Pid>, u64) {
+    let mut pids = Vec::new();
+    let mut total_memory = 0;
+    for proc in processes.values() {
+        if proc.parent() != Some(parent_pid) || !proc.exists() || !proc.is_real() {
+            continue;
+        }
+        pids.push(proc.pid());
+        total_memory += proc.memory();
+
+        let (child_pids, child_memory) = get_child_pids_and_total_memory_by_scan(processes, proc.pid());
+        pids.extend(child_pids);
+        total_memory += child_memory;
+    }
+    (pids, total_memory)
+}
+
+/// 读取 /proc/net/tcp 和 /proc/net/tcp6 中处于 LISTEN 状态的 socket，返回 inode -> 端口
+#[cfg(target_os = "linux")]
+pub fn listening_sockets() -> HashMap<u64, u16> {
+    const TCP_LISTEN: &str = "0A";
+    let mut sockets = HashMap::new();
+    for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(content) = fs::read_to_string(path) else {
+            continue;
+        };
+        // 格式：sl local_address rem_address st ... uid timeout inode
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                continue;
+            }
+            let Some((_, port)) = fields[1].rsplit_once(':') else {
+                continue;
+            };
+            if let (Ok(port), Ok(inode)) = (u16::from_str_radix(port, 16), fields[9].parse::<u64>()) {
+                sockets.insert(inode, port);
+            }
+        }
+    }
+    sockets
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn listening_sockets() -> HashMap<u64, u16> {
+    HashMap::new()
+}
+
+/// 这些进程打开的 socket 中正在监听的 TCP 端口，已排序去重
+pub fn listen_ports(sockets: &HashMap<u64, u16>, pids: &[u32]) -> Vec<u16> {
+    let mut ports = Vec::new();
+    if sockets.is_empty() {
+        return ports;
+    }
+    for pid in pids {
+        // /proc/[pid]/fd 下 socket 的链接目标为 socket:[inode]
+        let Ok(entries) = fs::read_dir(format!("/proc/{}/fd", pid)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(target) = fs::read_link(entry.path()) else {
+                continue;
+            };
+            let target = target.to_string_lossy();
+            if let Some(inode) = target.strip_prefix("socket:[").and_then(|s| s.strip_suffix(']'))
+                && let Ok(inode) = inode.parse::<u64>()
+                && let Some(port) = sockets.get(&inode)
+            {
+                ports.push(*port);
+            }
+        }
+    }
+    ports.sort();
+    ports.dedup();
+    ports
+}
+
+// 进程的内存采样结果
+struct MemorySample {
+    name: String,
+    memory: u64,                     // 进程及其子进程使用的内存，单位字节
+    restart_on_memory: Option<u32>,  // 进程配置的内存重启阈值，单位 MB
+    restart_on_memory_for: Duration, // 超过阈值需要持续的时长
+}
+
+// 每个运行中的进程（含子进程）使用的内存
+fn sample_memory(sys: &mut System, reg: &Registry) -> Vec<MemorySample> {
+    let running: Vec<_> = reg
+        .list()
+        .into_iter()
+        .filter(|p| p.pid != 0 && matches!(p.state, ProcState::Starting | ProcState::Running))
+        .collect();
+    if running.is_empty() {
+        return vec![];
+    }
+
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing().with_memory(),
+    );
+    let processes = sys.processes();
+    let children = build_children_index(processes);
+
+    running
+        .into_iter()
+        .map(|p| {
+            let pid = Pid::from_u32(p.pid);
+            let (_, child_memory) = get_child_pids_and_total_memory(processes, &children, pid);
+            let memory = processes.get(&pid).map(|x| x.memory()).unwrap_or(0);
+            MemorySample {
+                name: p.name,
+                memory: memory + child_memory,
+                restart_on_memory: p.cmd.restart_on_memory,
+                restart_on_memory_for: p.cmd.restart_on_memory_for,
+            }
+        })
+        .collect()
+}
+
+// 超过 total_memory_limit（MB）时按配置的动作处理
+async fn check_total_memory(reg: &Registry, samples: &[MemorySample], limit_mb: u64, action: MemoryAction) {
+    let total: u64 = samples.iter().map(|s| s.memory).sum();
+    if total <= limit_mb * 1024 * 1024 {
+        return;
+    }
+    let Some(largest) = samples.iter().max_by_key(|s| s.memory) else {
+        return;
+    };
+    tracing::warn!(
+        "total memory {:.1} MB exceeds total_memory_limit {} MB, largest: {} ({:.1} MB)",
+        total as f64 / 1024.0 / 1024.0,
+        limit_mb,
+        largest.name,
+        largest.memory as f64 / 1024.0 / 1024.0
+    );
+
+    if action == MemoryAction::KillLargest
+        && let Some(tx) = reg.get_control(&largest.name)
+        && tx.send(ControlMsg::Kill).await.is_err()
+    {
+        tracing::warn!("failed to kill {}: not supervised", largest.name);
+    }
+}
+
+// 进程内存持续超过 restart_on_memory 时重启；over_since 记录每个进程开始超限的时间
+async fn check_restart_on_memory(reg: &Registry, samples: &[MemorySample], over_since: &mut HashMap<String, Instant>) {
+    // 已退出或不再超限的进程重新计时
+    over_since.retain(|name, _| {
+        samples
+            .iter()
+            .any(|s| &s.name == name && s.restart_on_memory.is_some_and(|m| s.memory > m as u64 * 1024 * 1024))
+    });
+
+    for s in samples {
+        let Some(limit_mb) = s.restart_on_memory else {
+            continue;
+        };
+        if s.memory <= limit_mb as u64 * 1024 * 1024 {
+            continue;
+        }
+        let since = *over_since.entry(s.name.clone()).or_insert_with(Instant::now);
+        if since.elapsed() < s.restart_on_memory_for {
+            continue;
+        }
+
+        tracing::warn!(
+            "{} uses {:.1} MB, over restart_on_memory {} MB for {:?}, restarting",
+            s.name,
+            s.memory as f64 / 1024.0 / 1024.0,
+            limit_mb,
+            s.restart_on_memory_for
+        );
+        over_since.remove(&s.name);
+        if let Some(tx) = reg.get_control(&s.name)
+            && tx.send(ControlMsg::Restart("memory")).await.is_err()
+        {
+            tracing::warn!("failed to restart {}: not supervised", s.name);
+        }
+    }
+}
+
+// 定期采样被管理进程的内存，处理 total_memory_limit 和各进程的 restart_on_memory
+pub fn spawn_memory_sampler(reg: Arc<Registry>, total_limit_mb: Option<u64>, action: MemoryAction) {
+    if let Some(limit_mb) = total_limit_mb {
+        tracing::info!("total_memory_limit {} MB, action {:?}", limit_mb, action);
+    }
+    tokio::spawn(async move {
+        let mut sys = System::new();
+        let mut over_since: HashMap<String, Instant> = HashMap::new();
+        loop {
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+
+            // 同时记录总内存，供 /api/status 使用，不需要在请求时刷新全部进程
+            let samples = sample_memory(&mut sys, &reg);
+            reg.set_memory_used(samples.iter().map(|s| s.memory).sum());
+            if let Some(limit_mb) = total_limit_mb {
+                check_total_memory(&reg, &samples, limit_mb, action).await;
+            }
+            check_restart_on_memory(&reg, &samples, &mut over_since).await;
+        }
+    });
+}
@@ -1,17 +1,27 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::path::Path;
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::{sync::mpsc, time::Duration};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Duration,
+};
 
 use crate::{
-    config::ProcessConfig,
+    config::{MaxRunAction, OutputTarget, ProcessConfig, RestartPolicy, redact_env, secret_env_keys},
+    error::ProcdError,
     process::{
-        logger::pipe_logger,
-        registry::{ControlMsg, ProcState, Registry},
+        health,
+        hook::{run_hook, spawn_exit_hook},
+        logger::{OutputState, pipe_logger},
+        registry::{ControlMsg, HEARTBEAT_INTERVAL, ProcState, Registry},
     },
 };
 
 #[cfg(unix)]
 use {
+    crate::config::parse_signal,
     nix::sys::resource::{Resource, rlim_t, setrlimit},
     nix::sys::signal::{Signal, kill},
     nix::unistd::Pid,
@@ -28,6 +38,21 @@ fn kill_process(pid: u32) {
     let _ = kill(Pid::from_raw(pgid), Signal::SIGKILL);
 }
 
+// 向进程组发送 SIGTERM 或 max_run_signal，让进程有机会优雅退出
+#[cfg(unix)]
+fn term_process(pid: u32, signal: i32) {
+    if pid == 0 {
+        return;
+    }
+    let pgid = -(pid as i32);
+    if let Ok(signal) = Signal::try_from(signal) {
+        let _ = kill(Pid::from_raw(pgid), signal);
+    }
+}
+
+#[cfg(windows)]
+fn term_process(_pid: u32, _signal: i32) {}
+
 #[cfg(windows)]
 fn kill_process(pid: u32) {
     if pid == 0 {
@@ -40,13 +65,243 @@ fn kill_process(pid: u32) {
     }
 }
 
-fn spawn_process(pcfg: &ProcessConfig) -> anyhow::Result<std::process::Child> {
-    let mut cmd = pcfg.get_cmd();
+// 展示用的子进程环境变量，敏感的值和 secrets 中的变量（从 @file: 读取）已隐藏
+fn effective_envs(cmd: &std::process::Command, env_clear: bool, secrets: &HashSet<String>) -> Vec<String> {
+    command_envs(cmd, env_clear)
+        .iter()
+        .map(|(k, v)| {
+            let key = k.to_string_lossy();
+            match secrets.contains(key.as_ref()) {
+                true => format!("{}=******", key),
+                false => redact_env(&format!("{}={}", key, v.to_string_lossy())),
+            }
+        })
+        .collect()
+}
+
+// 子进程实际得到的环境变量：继承当前进程的环境变量（env_clear 时不继承），再叠加 Command 上设置的值
+fn command_envs(cmd: &std::process::Command, env_clear: bool) -> BTreeMap<OsString, OsString> {
+    let mut envs: BTreeMap<OsString, OsString> = BTreeMap::new();
+    if !env_clear {
+        envs.extend(std::env::vars_os());
+    }
+    for (k, v) in cmd.get_envs() {
+        match v {
+            Some(v) => envs.insert(k.to_os_string(), v.to_os_string()),
+            None => envs.remove(k),
+        };
+    }
+    envs
+}
+
+// 进程的退出信息：正常退出时有 code，被信号杀死时有 signal（仅 Unix）
+#[derive(Debug, Clone, Copy, Default)]
+struct ExitInfo {
+    code: Option<i32>,
+    signal: Option<i32>,
+}
+
+impl From<std::process::ExitStatus> for ExitInfo {
+    fn from(status: std::process::ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = {
+            use std::os::unix::process::ExitStatusExt;
+            status.signal()
+        };
+        #[cfg(windows)]
+        let signal = None;
+
+        ExitInfo {
+            code: status.code(),
+            signal,
+        }
+    }
+}
+
+// 信号名称，如 "SIGSEGV"，无法识别时返回数字
+fn signal_name(signal: i32) -> String {
+    #[cfg(unix)]
+    if let Ok(s) = Signal::try_from(signal) {
+        return s.as_str().to_string();
+    }
+    signal.to_string()
+}
+
+// 根据 restart 和 restart_on_signals 判断进程退出后是否需要重启
+fn should_restart(cfg: &ProcessConfig, signal: Option<i32>) -> bool {
+    if cfg.restart == RestartPolicy::Never {
+        return false;
+    }
+    if cfg.restart_on_signals.is_empty() {
+        return true;
+    }
+    let Some(signal) = signal else {
+        return false;
+    };
+    let name = signal_name(signal);
+    cfg.restart_on_signals.iter().any(|s| {
+        let s = s.trim().to_uppercase();
+        s == name || format!("SIG{}", s) == name || s == signal.to_string()
+    })
+}
+
+#[cfg(target_os = "linux")]
+const NETNS_DIR: &str = "/var/run/netns"; // ip netns add 创建的命名空间所在目录
+
+const KILL_WAIT: Duration = Duration::from_secs(5); // SIGKILL 后等待进程退出的时间
+
+// stop_process 停止进程
+// 配置了 term_timeout 时先发送 SIGTERM，在等待期间进程一旦退出（exit_rx 收到退出码）立即返回，超时后再 SIGKILL
+// 停止期间状态为 Stopping，返回时进程已经退出，由调用方设置 Stopped/Killed
+async fn stop_process(cfg: &ProcessConfig, registry: &Registry, pid: u32, exit_rx: &mut oneshot::Receiver<ExitInfo>) {
+    let graceful = cfg.term_timeout.map(|t| (libc::SIGTERM, t));
+    stop_process_with(cfg, registry, pid, exit_rx, graceful).await
+}
+
+const MAX_RUN_GRACE: Duration = Duration::from_secs(10); // 配置了 max_run_signal 但没有 term_timeout 时，等待退出的时长
+
+// 达到 max_run 时停止进程：配置了 max_run_signal 时先发送该信号，等待 term_timeout（默认 MAX_RUN_GRACE）后再 SIGKILL
+async fn stop_max_run(cfg: &ProcessConfig, registry: &Registry, pid: u32, exit_rx: &mut oneshot::Receiver<ExitInfo>) {
+    #[cfg(unix)]
+    if let Some(signal) = cfg.max_run_signal.as_deref().and_then(parse_signal) {
+        let grace = cfg.term_timeout.unwrap_or(MAX_RUN_GRACE);
+        return stop_process_with(cfg, registry, pid, exit_rx, Some((signal as i32, grace))).await;
+    }
+    stop_process(cfg, registry, pid, exit_rx).await
+}
+
+// graceful 为先发送的信号和等待的时长，为空时直接 SIGKILL
+async fn stop_process_with(
+    cfg: &ProcessConfig,
+    registry: &Registry,
+    pid: u32,
+    exit_rx: &mut oneshot::Receiver<ExitInfo>,
+    graceful: Option<(i32, Duration)>,
+) {
+    registry.set_state(&cfg.name, ProcState::Stopping);
+    if let Some((signal, timeout)) = graceful {
+        term_process(pid, signal);
+        match tokio::time::timeout(timeout, &mut *exit_rx).await {
+            Ok(_) => {
+                tracing::info!("exited after {}", signal_name(signal));
+                return;
+            }
+            Err(_) => tracing::warn!(
+                "still alive {:?} after {}, sending SIGKILL",
+                timeout,
+                signal_name(signal)
+            ),
+        }
+    }
+    // 必须等到 wait 线程收到退出结果才返回：否则调用方会在旧进程仍存活时启动新进程，
+    // 旧的 wait 线程也会一直阻塞，反复重启时不断累积；进程处于 D 状态等无法立即杀死时持续重试
+    let mut waited = Duration::ZERO;
+    loop {
+        kill_process(pid);
+        if tokio::time::timeout(KILL_WAIT, &mut *exit_rx).await.is_ok() {
+            return;
+        }
+        waited += KILL_WAIT;
+        tracing::error!("still alive {:?} after SIGKILL, retrying", waited);
+    }
+}
+
+const STOP_WAIT_EXTRA: Duration = Duration::from_secs(10); // 等待 supervise 循环退出时，在 term_timeout 之外额外等待的时间
+
+// 停止进程的 supervise 循环并等待它退出，用于 reload 和 procd 退出；进程没有被管理时直接返回 true
+pub async fn stop_supervised(reg: &Registry, cfg: &ProcessConfig, reason: &str) -> bool {
+    let Some(tx) = reg.get_control(&cfg.name) else {
+        return true;
+    };
+    if tx.is_closed() {
+        return true;
+    }
+    let _ = tx.send(ControlMsg::Kill).await;
+    let wait = cfg.term_timeout.unwrap_or_default() + STOP_WAIT_EXTRA;
+    let stopped = tokio::time::timeout(wait, tx.closed()).await.is_ok();
+    if stopped {
+        reg.set_stop_reason(&cfg.name, reason);
+    }
+    stopped
+}
+
+// 向进程发送 SIGHUP，让其自行重新加载配置
+#[cfg(unix)]
+pub fn reload_process(pid: u32) -> anyhow::Result<()> {
+    if pid == 0 {
+        anyhow::bail!("process is not running");
+    }
+    kill(Pid::from_raw(pid as i32), Signal::SIGHUP)?;
+    Ok(())
+}
+
+fn spawn_process(pcfg: &ProcessConfig, output: &OutputState) -> Result<(std::process::Child, Vec<String>), ProcdError> {
+    pcfg.check_allowed()?;
+    let mut cmd = pcfg.get_cmd()?;
     #[cfg(unix)]
     {
         let mem_limit = pcfg.memory_limit.unwrap_or(0);
         let name = pcfg.name.clone();
 
+        #[cfg(target_os = "linux")]
+        let cpu_set = cpu_set_of(&pcfg.cpu_affinity)?;
+        #[cfg(not(target_os = "linux"))]
+        if !pcfg.cpu_affinity.is_empty() {
+            tracing::warn!("cpu_affinity is only supported on linux, ignored");
+        }
+
+        // 在 fork 前打开 netns 文件，找不到时在这里报错；fd 带 CLOEXEC，不会泄漏给子进程
+        #[cfg(target_os = "linux")]
+        let netns = match &pcfg.netns {
+            Some(ns) => Some(open_netns(ns)?),
+            None => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        if pcfg.netns.is_some() {
+            tracing::warn!("netns is only supported on linux, ignored");
+        }
+
+        // chroot 在 pre_exec 中调用，需要的 CString 在 fork 前准备好
+        if let Some(root) = &pcfg.chroot
+            && !Path::new(root).is_dir()
+        {
+            return Err(anyhow::anyhow!("chroot {} is not a directory", root).into());
+        }
+        let chroot = match &pcfg.chroot {
+            Some(root) => Some((
+                std::ffi::CString::new(root.as_str()).map_err(anyhow::Error::from)?,
+                std::ffi::CString::new(pcfg.chroot_home()).map_err(anyhow::Error::from)?,
+            )),
+            None => None,
+        };
+
+        // seccomp_profile 无法加载时不启动，避免进程在没有过滤规则的情况下运行
+        #[cfg(all(target_os = "linux", feature = "seccomp"))]
+        let seccomp_filter = match &pcfg.seccomp_profile {
+            Some(path) => Some(super::seccomp::load_profile(path)?),
+            None => None,
+        };
+        #[cfg(not(all(target_os = "linux", feature = "seccomp")))]
+        if pcfg.seccomp_profile.is_some() {
+            return Err(anyhow::anyhow!("seccomp_profile requires linux and the seccomp feature").into());
+        }
+
+        // socket activation：监听 socket 在 fork 前打开，pre_exec 最后把它传给子进程并自己 exec
+        let mut activated = match &pcfg.socket {
+            Some(addr) => {
+                let fd = super::socket::listen_fd(addr)?;
+                cmd.env("LISTEN_FDS", "1").env("LISTEN_PID", "");
+                let envs = command_envs(&cmd, pcfg.env_clear);
+                Some(super::socket::ActivatedExec::new(
+                    fd,
+                    &cmd,
+                    pcfg.arg0.as_deref(),
+                    &envs,
+                )?)
+            }
+            None => None,
+        };
+
         unsafe {
             cmd.pre_exec(move || {
                 libc::setsid();
@@ -55,6 +310,12 @@ fn spawn_process(pcfg: &ProcessConfig) -> anyhow::Result<std::process::Child> {
                 #[cfg(target_os = "linux")]
                 {
                     libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
+                    if let Some(cpu_set) = &cpu_set {
+                        nix::sched::sched_setaffinity(Pid::from_raw(0), cpu_set)?;
+                    }
+                    if let Some(netns) = &netns {
+                        nix::sched::setns(netns, nix::sched::CloneFlags::CLONE_NEWNET)?;
+                    }
                 }
 
                 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -64,12 +325,34 @@ fn spawn_process(pcfg: &ProcessConfig) -> anyhow::Result<std::process::Child> {
                     }
                 }
 
+                if let Some((root, home)) = &chroot {
+                    nix::unistd::chroot(root.as_c_str())?;
+                    nix::unistd::chdir(home.as_c_str())?;
+                }
+
+                // 最后加载 seccomp，之前的 setns、setrlimit 等调用不受过滤规则限制
+                #[cfg(all(target_os = "linux", feature = "seccomp"))]
+                if let Some(filter) = &seccomp_filter {
+                    super::seccomp::apply(filter)?;
+                }
+
+                // 成功时不会返回，后面 std 自己的 exec 不再执行
+                if let Some(activated) = &mut activated {
+                    return Err(activated.exec());
+                }
+
                 Ok(())
             });
         }
     }
 
-    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    // 两个输出流的去向分别配置，null 时直接丢弃，不再创建管道和读取线程
+    let stdio = |kind| match pcfg.output_target(kind) {
+        OutputTarget::Null => Stdio::null(),
+        _ => Stdio::piped(),
+    };
+    cmd.stdout(stdio("stdout")).stderr(stdio("stderr"));
+    let mut envs = effective_envs(&cmd, pcfg.env_clear, &secret_env_keys(&pcfg.envs));
 
     let pid: u32;
 
@@ -80,20 +363,71 @@ fn spawn_process(pcfg: &ProcessConfig) -> anyhow::Result<std::process::Child> {
             child
         }
         Result::Err(e) => {
-            let msg = format!("spawn_process [ {:?} ] faild: {:?}", cmd, e);
-            tracing::error!("{}", msg);
-            return Err(anyhow::Error::new(e).context(msg));
+            tracing::error!("spawn_process [ {:?} ] faild: {:?}", cmd, e);
+            let program = cmd.get_program().to_string_lossy().to_string();
+            return Err(match e.kind() {
+                std::io::ErrorKind::NotFound => ProcdError::CommandNotFound { cmd: program },
+                std::io::ErrorKind::PermissionDenied => {
+                    let mut hint = String::new();
+                    if pcfg.netns.is_some() {
+                        hint += ", joining a netns requires CAP_SYS_ADMIN (run procd as root)";
+                    }
+                    if pcfg.chroot.is_some() {
+                        hint += ", chroot requires CAP_SYS_CHROOT (run procd as root)";
+                    }
+                    ProcdError::PermissionDenied { cmd: program, hint }
+                }
+                _ => ProcdError::Spawn {
+                    cmd: program,
+                    source: e,
+                },
+            });
         }
     };
 
+    // LISTEN_PID 在 fork 后才填写，展示时补上
+    if let Some(e) = envs.iter_mut().find(|e| e.as_str() == "LISTEN_PID=") {
+        *e = format!("LISTEN_PID={}", pid);
+    }
+
     if let Some(stdout) = child.stdout.take() {
-        pipe_logger(stdout, pcfg.clone(), pid, "stdout");
+        pipe_logger(stdout, pcfg.clone(), pid, "stdout", output.clone());
     }
     if let Some(stderr) = child.stderr.take() {
-        pipe_logger(stderr, pcfg.clone(), pid, "stderr");
+        pipe_logger(stderr, pcfg.clone(), pid, "stderr", output.clone());
+    }
+
+    Ok((child, envs))
+}
+
+// 打开 ip netns 创建的命名网络命名空间
+#[cfg(target_os = "linux")]
+fn open_netns(name: &str) -> anyhow::Result<std::fs::File> {
+    if name.contains('/') {
+        anyhow::bail!("invalid netns name {:?}", name);
     }
+    let path = Path::new(NETNS_DIR).join(name);
+    std::fs::File::open(&path).map_err(|e| {
+        anyhow::anyhow!(
+            "failed to open netns {:?}: {}, create it first with `ip netns add {}`",
+            path,
+            e,
+            name
+        )
+    })
+}
 
-    Ok(child)
+// 在 fork 前构建 CpuSet，pre_exec 中只调用 sched_setaffinity
+#[cfg(target_os = "linux")]
+fn cpu_set_of(cores: &[usize]) -> anyhow::Result<Option<nix::sched::CpuSet>> {
+    if cores.is_empty() {
+        return Ok(None);
+    }
+    let mut cpu_set = nix::sched::CpuSet::new();
+    for core in cores {
+        cpu_set.set(*core)?;
+    }
+    Ok(Some(cpu_set))
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
@@ -103,43 +437,195 @@ fn setup_memory_limit(name: &str, mem_limit_mb: u32) -> std::io::Result<()> {
     }
 
     let bytes: rlim_t = (mem_limit_mb * 1024 * 1024) as u64;
-    setrlimit(Resource::RLIMIT_AS, bytes, bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+    setrlimit(Resource::RLIMIT_AS, bytes, bytes).map_err(std::io::Error::other)?;
     tracing::info!("{}: memory limit set to {} MB", name, mem_limit_mb);
     Ok(())
 }
 
+// 执行 pre_start 钩子，失败时返回错误信息
+async fn run_pre_start(cfg: &ProcessConfig) -> Option<String> {
+    let hook = cfg.pre_start.as_ref()?;
+    match run_hook(cfg, "pre_start", hook, &[("PROCD_NAME", cfg.name.clone())]).await {
+        Ok(0) => None,
+        Ok(code) => Some(format!("pre_start exited with {}", code)),
+        Err(e) => Some(format!("{:?}", e)),
+    }
+}
+
+// 运行 restart_cmd 原地重启进程，等待期间继续更新心跳；未配置或失败时返回 false，由调用方杀死并重新启动
+async fn run_restart_cmd(cfg: &ProcessConfig, registry: &Registry, pid: u32, reason: &str) -> bool {
+    let Some(restart_cmd) = &cfg.restart_cmd else {
+        return false;
+    };
+    let envs = [
+        ("PROCD_NAME", cfg.name.clone()),
+        ("PROCD_PID", pid.to_string()),
+        ("PROCD_RESTART_REASON", reason.to_string()),
+    ];
+    let run = run_hook(cfg, "restart_cmd", restart_cmd, &envs);
+    tokio::pin!(run);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let result = loop {
+        tokio::select! {
+            result = &mut run => break result,
+            _ = heartbeat.tick() => registry.heartbeat(&cfg.name),
+        }
+    };
+    match result {
+        Ok(0) => {
+            tracing::info!("restart_cmd succeeded, process keeps running");
+            registry.set_reloaded(&cfg.name);
+            true
+        }
+        Ok(code) => {
+            tracing::warn!("restart_cmd exited with {}, falling back to kill and respawn", code);
+            false
+        }
+        Err(e) => {
+            tracing::warn!("restart_cmd failed, falling back to kill and respawn: {:#}", e);
+            false
+        }
+    }
+}
+
+// blue-green 重启：旧实例继续运行，先启动新实例，等到它重新创建或更新 ready_file 后返回
+// 等待期间继续更新心跳；新实例未能就绪时杀死它并返回错误
+async fn start_standby(
+    cfg: &ProcessConfig,
+    registry: &Registry,
+    output: &OutputState,
+) -> anyhow::Result<(std::process::Child, Vec<String>)> {
+    let Some(file) = &cfg.ready_file else {
+        anyhow::bail!("blue-green restart requires ready_file");
+    };
+    let path = Path::new(&cfg.home).join(file);
+    if let Some(msg) = run_pre_start(cfg).await {
+        anyhow::bail!(msg);
+    }
+
+    let since = std::time::SystemTime::now();
+    let (mut child, envs) = spawn_process(cfg, output)?;
+    let pid = child.id();
+    tracing::info!("standby instance {} started, waiting for {:?}", pid, path);
+
+    let ready = health::wait_ready_file_since(&path, since, cfg.ready_timeout);
+    tokio::pin!(ready);
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let ready = loop {
+        tokio::select! {
+            ready = &mut ready => break ready,
+            _ = heartbeat.tick() => registry.heartbeat(&cfg.name),
+        }
+    };
+    if ready {
+        return Ok((child, envs));
+    }
+
+    kill_process(pid);
+    let _ = tokio::task::spawn_blocking(move || child.wait()).await;
+    anyhow::bail!("standby instance {} not ready after {:?}", pid, cfg.ready_timeout)
+}
+
+// 启动失败后等待 1 秒再重试，期间仍然响应控制消息；收到 kill 时返回 true，supervise 循环应退出
+async fn wait_retry(cfg: &ProcessConfig, registry: &Registry, rx: &mut mpsc::Receiver<ControlMsg>) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(Duration::from_secs(1)) => false,
+        Some(cmd) = rx.recv() => match cmd {
+            ControlMsg::Restart(reason) => {
+                tracing::info!("received restart ({}), retrying now", reason);
+                false
+            }
+            ControlMsg::BlueGreen => {
+                tracing::info!("received blue-green restart, retrying now");
+                false
+            }
+            ControlMsg::Kill => {
+                tracing::info!("received kill");
+                registry.set_state(&cfg.name, ProcState::Killed);
+                registry.set_stop_reason(&cfg.name, "kill");
+                true
+            }
+        },
+    }
+}
+
 pub async fn supervise(cfg: ProcessConfig, registry: Arc<Registry>) {
+    let Some(rx) = registry.register_process(&cfg.name, cfg.clone()) else {
+        tracing::warn!(name = cfg.name, "another supervise loop is running, skipped");
+        return;
+    };
+    supervise_registered(cfg, registry, rx).await
+}
+
+// supervise_registered 运行已经通过 register_process 注册的 supervise 循环；
+// API 启动进程时先在 handler 中注册，并发的启动请求只有一个能注册成功
+pub async fn supervise_registered(cfg: ProcessConfig, registry: Arc<Registry>, mut rx: mpsc::Receiver<ControlMsg>) {
     let worker_span = tracing::span!(tracing::Level::INFO, "worker", name = cfg.name);
     let _enter = worker_span.enter();
 
-    let (tx, mut rx) = mpsc::channel::<ControlMsg>(8);
-    registry.register_process(&cfg.name, cfg.clone(), tx);
     if !cfg.enable {
         tracing::warn!(name = cfg.name, "enable=false, skipped");
         return;
     }
+    let output = registry.find(&cfg.name).unwrap().output;
 
-    // 如果 cfg.next 有值
+    // 如果 cfg.next 有值，记录下次运行的时间后等待
     let wait_next = || async {
         if let Some(next) = cfg.next {
+            if let Ok(delta) = chrono::TimeDelta::from_std(next) {
+                registry.set_next_run(&cfg.name, chrono::Local::now() + delta);
+            }
             tokio::time::sleep(next).await;
         }
     };
 
+    // 首次启动和通过 API 手动重启不计入自动重启次数
+    let mut auto_restart = false;
+    // blue-green 重启时已就绪的新实例，下一轮循环直接使用
+    let mut standby: Option<(std::process::Child, Vec<String>)> = None;
     loop {
         let start_time = tokio::time::Instant::now();
 
-        let child = match spawn_process(&cfg) {
+        if auto_restart && !registry.record_restart(&cfg.name) {
+            let max = cfg.max_restarts.unwrap_or(0);
+            let msg = format!("restarted {} times within {:?}, giving up", max, cfg.restart_window);
+            tracing::warn!("{}", msg);
+            registry.set_state(&cfg.name, ProcState::Error(msg));
+            return;
+        }
+        auto_restart = true;
+
+        // blue-green 重启时新实例已经启动并就绪，直接接管
+        let standby_child = standby.take();
+        if standby_child.is_none()
+            && let Some(msg) = run_pre_start(&cfg).await
+        {
+            tracing::warn!("{}", msg);
+            registry.set_state(&cfg.name, ProcState::Error(msg));
+            // pre_start 失败，和启动失败一样等待 1 秒后重试；restart=never 时保持 Error 并结束
+            if cfg.restart == RestartPolicy::Never || wait_retry(&cfg, &registry, &mut rx).await {
+                return;
+            }
+            continue;
+        }
+
+        let spawned = match standby_child {
+            Some(c) => Ok(c),
+            None => spawn_process(&cfg, &output),
+        };
+        let (child, envs) = match spawned {
             Ok(c) => c,
             Err(e) => {
                 registry.set_state(&cfg.name, ProcState::Error(e.to_string()));
-                // 若启动失败，则等待 1 秒后重试
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                // 若启动失败，则等待 1 秒后重试；restart=never 只运行一次，不再重试，--oneshot 可以结束
+                if cfg.restart == RestartPolicy::Never || wait_retry(&cfg, &registry, &mut rx).await {
+                    return;
+                }
                 continue;
             }
         };
         let pid = child.id();
-        registry.set_running(&cfg.name, pid);
+        registry.set_running(&cfg.name, pid, envs);
 
         let span1 = tracing::span!(parent:&worker_span,tracing::Level::INFO,"pid",pid);
         let _enter1 = span1.enter();
@@ -147,13 +633,15 @@ pub async fn supervise(cfg: ProcessConfig, registry: Arc<Registry>) {
         tracing::info!("running");
 
         // 用 oneshot 接收 wait 结果
-        let (exit_tx, mut exit_rx) = tokio::sync::oneshot::channel();
+        let (exit_tx, mut exit_rx) = oneshot::channel();
 
         // 把 wait 放到 blocking 线程，并且只在那里持有 child
+        // 每次启动只有一个 wait 线程和一个 exit_rx，退出结果只会被下面的 select 或 stop_process 处理一次，
+        // 且 stop_process 返回前一定已收到退出结果，所以重启前旧的 wait 线程已经结束
         let mut wait_child = child;
         tokio::task::spawn_blocking(move || {
-            let code = wait_child.wait().ok().and_then(|s| s.code()).unwrap_or(-1);
-            let _ = exit_tx.send(code);
+            let info = wait_child.wait().map(ExitInfo::from).unwrap_or_default();
+            let _ = exit_tx.send(info);
         });
 
         // 如果 cfg.max_run 有值，创建超时 future
@@ -164,41 +652,171 @@ pub async fn supervise(cfg: ProcessConfig, registry: Arc<Registry>) {
             tokio::time::sleep(Duration::from_secs(u64::MAX))
         };
 
-        tokio::select! {
-            // 子进程自然退出
-            Result::Ok(code) = &mut exit_rx => {
-                registry.set_state(&cfg.name, ProcState::Exited(code));
-                tracing::info!(code,"exited");
-                wait_next().await;
+        // 配置了 ready_file 时先等待其出现，之后执行健康检查；返回需要重启进程的原因
+        let monitor_fut = async {
+            if let Some(file) = &cfg.ready_file {
+                let path = Path::new(&cfg.home).join(file);
+                if !health::wait_ready_file(&path, cfg.ready_timeout).await {
+                    tracing::warn!("ready_file {:?} not found after {:?}", path, cfg.ready_timeout);
+                    return "not_ready";
+                }
+                tracing::info!("ready_file {:?} found", path);
+                registry.set_state(&cfg.name, ProcState::Running);
             }
+            // 配置了健康检查时，连续失败达到上限后返回
+            match &cfg.health_check {
+                Some(hc) => health::watch(&cfg, hc, registry.clone()).await,
+                None => std::future::pending().await,
+            }
+            "unhealthy"
+        };
+
+        // 配置了 start_timeout 时，到期后进程仍处于 Starting 则视为启动失败
+        let start_timeout_fut = async {
+            let Some(timeout) = cfg.start_timeout else {
+                return std::future::pending().await;
+            };
+            tokio::time::sleep(timeout).await;
+            let starting = registry
+                .find(&cfg.name)
+                .is_some_and(|pe| pe.state == ProcState::Starting);
+            if !starting {
+                std::future::pending::<()>().await;
+            }
+        };
 
-            // 收到控制命令
-            Some(cmd) = rx.recv() => {
-                match cmd {
-                    ControlMsg::Restart  => {
-                        tracing::info!("received restart");
-                        kill_process(pid);
-                        registry.set_state(&cfg.name, ProcState::Stopped);
-                        // 主动重启的，不需要 wait_next
+        // 持续运行 min_uptime 后视为启动成功
+        let stable_fut = tokio::time::sleep(cfg.min_uptime);
+        let mut stable = false;
+
+        tokio::pin!(max_run_fut);
+        tokio::pin!(monitor_fut);
+        tokio::pin!(start_timeout_fut);
+        tokio::pin!(stable_fut);
+
+        // 定期更新心跳，watchdog 据此判断 supervise 循环是否卡住
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+
+        loop {
+            tokio::select! {
+                _ = heartbeat.tick() => {
+                    registry.heartbeat(&cfg.name);
+                    continue;
+                }
+
+                _ = &mut stable_fut, if !stable => {
+                    stable = true;
+                    registry.mark_stable(&cfg.name);
+                    continue;
+                }
+
+                // 子进程自然退出
+                info = &mut exit_rx => {
+                    // wait 线程异常退出时没有退出信息，也按进程已退出处理
+                    let info = info.unwrap_or_default();
+                    registry.set_state(&cfg.name, ProcState::Exited { code: info.code, signal: info.signal });
+                    tracing::info!(code = info.code, signal = info.signal, "exited");
+                    registry.set_stop_reason(&cfg.name, "exited");
+                    spawn_exit_hook(&cfg, info.code.unwrap_or(-1), info.signal, "exited");
+
+                    if !should_restart(&cfg, info.signal) {
+                        tracing::info!(
+                            "restart={:?}, restart_on_signals={:?}, stay stopped",
+                            cfg.restart,
+                            cfg.restart_on_signals
+                        );
+                        return;
                     }
-                    ControlMsg::Kill =>{
-                        tracing::info!("received kill");
-                        kill_process(pid);
-                        registry.set_state(&cfg.name, ProcState::Killed);
-                        return   // 主动杀死的，退出循环
+                    wait_next().await;
+                }
+
+                // 收到控制命令
+                Some(cmd) = rx.recv() => {
+                    match cmd {
+                        ControlMsg::Restart(reason) => {
+                            tracing::info!("received restart ({})", reason);
+                            // 配置了 restart_cmd 时先尝试原地重启，成功则进程继续运行；内存超限时必须重新启动才能释放内存
+                            if reason != "memory" && run_restart_cmd(&cfg, &registry, pid, reason).await {
+                                continue;
+                            }
+                            stop_process(&cfg, &registry, pid, &mut exit_rx).await;
+                            registry.set_state(&cfg.name, ProcState::Stopped);
+                            registry.set_stop_reason(&cfg.name, reason);
+                            spawn_exit_hook(&cfg, -1, None, reason);
+                            // 主动重启的，不需要 wait_next
+                            auto_restart = false;
+                        }
+                        ControlMsg::BlueGreen => {
+                            tracing::info!("received blue-green restart");
+                            match start_standby(&cfg, &registry, &output).await {
+                                Ok(new) => {
+                                    tracing::info!("standby instance {} is ready, stopping the old one", new.0.id());
+                                    stop_process(&cfg, &registry, pid, &mut exit_rx).await;
+                                    registry.set_state(&cfg.name, ProcState::Stopped);
+                                    registry.set_stop_reason(&cfg.name, "blue_green");
+                                    spawn_exit_hook(&cfg, -1, None, "blue_green");
+                                    standby = Some(new);
+                                    auto_restart = false;
+                                }
+                                Err(e) => {
+                                    // 新实例没有就绪，旧实例继续运行
+                                    tracing::error!("blue-green restart failed, keep the old instance: {:#}", e);
+                                    continue;
+                                }
+                            }
+                        }
+                        ControlMsg::Kill =>{
+                            tracing::info!("received kill");
+                            stop_process(&cfg, &registry, pid, &mut exit_rx).await;
+                            registry.set_state(&cfg.name, ProcState::Killed);
+                            registry.set_stop_reason(&cfg.name, "kill");
+                            spawn_exit_hook(&cfg, -1, None, "kill");
+                            return   // 主动杀死的，退出循环
+                        }
                     }
                 }
-            }
 
-            // 达到最大运行时长
-            _ = max_run_fut => {
-                let elapsed = start_time.elapsed();
-                tracing::info!("reached max_run_time (live={:?}), killing process",elapsed);
-                kill_process(pid);
-                registry.set_state(&cfg.name, ProcState::Stopped);
-                wait_next().await;
-            }
+                // 达到最大运行时长
+                _ = &mut max_run_fut => {
+                    let elapsed = start_time.elapsed();
+                    tracing::info!("reached max_run_time (live={:?}), stopping process",elapsed);
+                    stop_max_run(&cfg, &registry, pid, &mut exit_rx).await;
+                    registry.set_state(&cfg.name, ProcState::Stopped);
+                    registry.set_stop_reason(&cfg.name, "max_run");
+                    spawn_exit_hook(&cfg, -1, None, "max_run");
+                    if cfg.restart == RestartPolicy::Never || cfg.max_run_action == MaxRunAction::Stop {
+                        tracing::info!("max_run_action={:?}, stay stopped", cfg.max_run_action);
+                        return;
+                    }
+                    wait_next().await;
+                }
+
+                // 超过 start_timeout 仍未就绪
+                _ = &mut start_timeout_fut => {
+                    tracing::error!("still starting after {:?}, killing process", cfg.start_timeout.unwrap_or_default());
+                    stop_process(&cfg, &registry, pid, &mut exit_rx).await;
+                    registry.set_state(&cfg.name, ProcState::Error("start timeout".to_string()));
+                    registry.set_stop_reason(&cfg.name, "start_timeout");
+                    spawn_exit_hook(&cfg, -1, None, "start_timeout");
+                    if cfg.restart == RestartPolicy::Never {
+                        return;
+                    }
+                }
 
+                // 未能在 ready_timeout 内就绪，或健康检查连续失败
+                reason = &mut monitor_fut => {
+                    tracing::warn!("{}, restarting process", reason);
+                    stop_process(&cfg, &registry, pid, &mut exit_rx).await;
+                    registry.set_state(&cfg.name, ProcState::Stopped);
+                    registry.set_stop_reason(&cfg.name, reason);
+                    spawn_exit_hook(&cfg, -1, None, reason);
+                    if cfg.restart == RestartPolicy::Never {
+                        return;
+                    }
+                }
+
+            }
+            break;
         }
 
         let elapsed = start_time.elapsed();
@@ -0,0 +1,58 @@
+// HTTP API 的集成测试：认证和受限账号的访问范围
+mod common;
+
+use common::{ADMIN, Procd, mock_config, state};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+const WEB_USER: (&str, &str) = ("web", "web-secret"); // 只能访问名称以 web 开头的进程
+
+// 测试程序作为 mock 进程启动时的入口，普通测试运行时什么都不做
+#[test]
+fn mock_process() {
+    common::run_mock();
+}
+
+fn auth_config() -> String {
+    format!(
+        "auth:\n  username: {}\n  password: {}\n  users:\n    - username: {}\n      password: {}\n      processes: [web]\n",
+        ADMIN.0, ADMIN.1, WEB_USER.0, WEB_USER.1
+    )
+}
+
+#[test]
+fn limited_user_cannot_add_process_by_reload_config() {
+    let procd = Procd::start_with(&auth_config(), &[mock_config("web-1", &[], &[])]);
+    procd.wait_for("web-1", TIMEOUT, |p| state(p) == "Running");
+
+    // 配置文件中新增的进程尚未注册，check_scope 无法按 registry 检查
+    let config = procd.path("procd.yml");
+    let mut yaml = common::read_to_string(&config);
+    yaml.push_str(&mock_config("db", &[], &[]));
+    yaml.push_str(&mock_config("web-2", &[], &[]));
+    std::fs::write(&config, yaml).unwrap();
+
+    let (status, body) = procd.request_as(WEB_USER, "POST", "/api/process/db/reload-config");
+    assert_eq!(status, 403, "{}", body);
+    let (status, _) = procd.get("/api/process/db");
+    assert_eq!(status, 404, "db should not be added");
+
+    // 访问范围内的新进程可以添加
+    let (status, body) = procd.request_as(WEB_USER, "POST", "/api/process/web-2/reload-config");
+    assert_eq!(status, 200, "{}", body);
+    procd.wait_for("web-2", TIMEOUT, |p| state(p) == "Running");
+}
+
+#[test]
+fn limited_user_cannot_read_global_status() {
+    let procd = Procd::start_with(&auth_config(), &[mock_config("web-1", &[], &[])]);
+    for path in ["/api/status", "/api/self/metrics"] {
+        let (status, body) = procd.request_as(WEB_USER, "GET", path);
+        assert_eq!(status, 403, "{}: {}", path, body);
+        let (status, body) = procd.get(path);
+        assert_eq!(status, 200, "{}: {}", path, body);
+    }
+    // 自己范围内的进程仍然可以查看
+    let (status, body) = procd.request_as(WEB_USER, "GET", "/api/process/web-1");
+    assert_eq!(status, 200, "{}", body);
+}
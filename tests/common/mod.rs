@@ -0,0 +1,274 @@
+// 集成测试的公共部分：在临时目录中启动 oh-procd、通过 HTTP API 查询和控制进程，以及被管理的 mock 进程
+// mock 进程就是测试程序自己：以 mock_process 为过滤条件运行，并通过环境变量 MOCK_* 指定行为
+#![allow(dead_code)]
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const MOCK_ENV: &str = "OH_PROCD_MOCK"; // 设置时测试程序作为 mock 进程运行
+const MOCK_MAX_LIFETIME: Duration = Duration::from_secs(60); // oh-procd 异常退出时，mock 进程最多运行这么久
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10); // 等待 oh-procd 的 HTTP 服务可用的最长时间
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+pub const ADMIN: (&str, &str) = ("admin", "admin-secret"); // 配置了 auth 时，测试使用的主账号
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+fn env_ms(name: &str) -> Option<Duration> {
+    std::env::var(name).ok()?.parse().ok().map(Duration::from_millis)
+}
+
+// run_mock 在测试程序作为 mock 进程启动时执行 MOCK_* 指定的行为，之后退出，不会返回；普通测试运行时直接返回
+// MOCK_IGNORE_TERM=1    忽略 SIGTERM
+// MOCK_STDOUT/STDERR    启动后输出一行
+// MOCK_STDERR_PARTIAL   启动后向 stderr 输出，不带换行
+// MOCK_READY_FILE       启动 MOCK_READY_AFTER 毫秒后创建该文件
+// MOCK_EXIT_AFTER       运行该毫秒数后以 MOCK_EXIT_CODE（默认 0）退出，不设置时一直运行
+pub fn run_mock() {
+    if std::env::var_os(MOCK_ENV).is_none() {
+        return;
+    }
+    if std::env::var_os("MOCK_IGNORE_TERM").is_some() {
+        unsafe {
+            libc::signal(libc::SIGTERM, libc::SIG_IGN);
+        }
+    }
+    if let Ok(line) = std::env::var("MOCK_STDOUT") {
+        println!("{}", line);
+    }
+    if let Ok(line) = std::env::var("MOCK_STDERR") {
+        eprintln!("{}", line);
+    }
+    if let Ok(text) = std::env::var("MOCK_STDERR_PARTIAL") {
+        eprint!("{}", text); // stderr 不带缓冲，立即写出
+    }
+    let start = Instant::now();
+    if let Ok(file) = std::env::var("MOCK_READY_FILE") {
+        std::thread::sleep(env_ms("MOCK_READY_AFTER").unwrap_or_default());
+        std::fs::write(file, b"ready").unwrap();
+    }
+    let run = env_ms("MOCK_EXIT_AFTER").unwrap_or(MOCK_MAX_LIFETIME);
+    std::thread::sleep(run.saturating_sub(start.elapsed()));
+    let code = std::env::var("MOCK_EXIT_CODE")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(0);
+    std::process::exit(code);
+}
+
+// mock 进程的配置，envs 为 MOCK_* 环境变量，extra 为追加到该进程下的配置行（不需要缩进）
+pub fn mock_config(name: &str, envs: &[(&str, &str)], extra: &[&str]) -> String {
+    let exe = std::env::current_exe().unwrap();
+    let mut envs: Vec<String> = envs.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    envs.insert(0, format!("{}=1", MOCK_ENV));
+    let mut yaml = format!(
+        "  - name: {}\n    cmd: {}\n    args: [\"mock_process\", \"--exact\", \"--nocapture\", \"-q\"]\n    envs: {}\n",
+        name,
+        exe.display(),
+        serde_json::to_string(&envs).unwrap()
+    );
+    for line in extra {
+        yaml.push_str(&format!("    {}\n", line));
+    }
+    yaml
+}
+
+// 在临时目录中运行的 oh-procd，drop 时停止并删除目录
+pub struct Procd {
+    child: Child,
+    port: u16,
+    pub dir: PathBuf,
+}
+
+impl Procd {
+    // processes 为 mock_config 生成的进程配置
+    pub fn start(processes: &[String]) -> Procd {
+        Procd::start_with("", processes)
+    }
+
+    // global 为追加到 http 之后的全局配置，如 auth；配置 auth 时主账号应使用 ADMIN
+    pub fn start_with(global: &str, processes: &[String]) -> Procd {
+        let dir = std::env::temp_dir().join(format!(
+            "oh-procd-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // 先占用一个空闲端口再释放，交给 oh-procd 使用
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = format!(
+            "http:\n  addr: 127.0.0.1:{}\n{}process:\n{}",
+            port,
+            global,
+            processes.concat()
+        );
+        std::fs::write(dir.join("procd.yml"), config).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_oh-procd"))
+            .arg("-c")
+            .arg(dir.join("procd.yml"))
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(std::fs::File::create(dir.join("procd.log")).unwrap())
+            .spawn()
+            .unwrap();
+        let procd = Procd { child, port, dir };
+
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while procd.try_request("GET", "/api/processes").is_err() {
+            assert!(Instant::now() < deadline, "oh-procd did not start, see {:?}", procd.dir);
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        procd
+    }
+
+    // 以 user 的身份发送一个 HTTP/1.1 请求，返回状态码和响应 body；没有配置 auth 时 oh-procd 忽略认证信息
+    fn try_request_as(&self, user: (&str, &str), method: &str, path: &str) -> std::io::Result<(u16, String)> {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port))?;
+        let credentials = STANDARD.encode(format!("{}:{}", user.0, user.1));
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: 127.0.0.1\r\nAuthorization: Basic {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            method, path, credentials
+        )?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        let status = head.split(' ').nth(1).and_then(|s| s.parse().ok()).unwrap_or(0);
+        // 长度未知的响应（如 JSON）可能使用 chunked 编码
+        let chunked = head.to_ascii_lowercase().contains("transfer-encoding: chunked");
+        Ok((status, if chunked { dechunk(body) } else { body.to_string() }))
+    }
+
+    fn try_request(&self, method: &str, path: &str) -> std::io::Result<(u16, String)> {
+        self.try_request_as(ADMIN, method, path)
+    }
+
+    pub fn get(&self, path: &str) -> (u16, String) {
+        self.try_request("GET", path).unwrap()
+    }
+
+    pub fn post(&self, path: &str) -> (u16, String) {
+        self.try_request("POST", path).unwrap()
+    }
+
+    // 以 auth.users 中的受限账号发送请求
+    pub fn request_as(&self, user: (&str, &str), method: &str, path: &str) -> (u16, String) {
+        self.try_request_as(user, method, path).unwrap()
+    }
+
+    // 进程的详情，即 GET /api/process/{name} 的响应
+    pub fn process(&self, name: &str) -> Value {
+        let (status, body) = self.try_request("GET", &format!("/api/process/{}", name)).unwrap();
+        assert_eq!(status, 200, "GET /api/process/{}: {}", name, body);
+        serde_json::from_str(&body).unwrap()
+    }
+
+    // 轮询进程详情，直到 pred 成立，超时后 panic 并输出最后一次的详情
+    pub fn wait_for(&self, name: &str, timeout: Duration, pred: impl Fn(&Value) -> bool) -> Value {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let detail = self.process(name);
+            if pred(&detail) {
+                return detail;
+            }
+            assert!(Instant::now() < deadline, "timed out waiting for {}: {}", name, detail);
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    // oh-procd 的直接子进程，即没有使用沙盒时被管理的进程
+    pub fn children(&self) -> Vec<u64> {
+        children_of(self.child.id() as u64)
+    }
+
+    // oh-procd 当前的线程数，即 /proc/[pid]/task 下的条目数，仅 Linux
+    pub fn threads(&self) -> usize {
+        std::fs::read_dir(format!("/proc/{}/task", self.child.id()))
+            .map(|d| d.count())
+            .unwrap_or(0)
+    }
+
+    pub fn path(&self, file: &str) -> PathBuf {
+        self.dir.join(file)
+    }
+}
+
+impl Drop for Procd {
+    fn drop(&mut self) {
+        // SIGTERM 让 oh-procd 停止全部子进程后退出，超时后直接杀死
+        unsafe {
+            libc::kill(self.child.id() as i32, libc::SIGTERM);
+        }
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        while self.child.try_wait().ok().flatten().is_none() {
+            if Instant::now() > deadline {
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                break;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        if !std::thread::panicking() {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn dechunk(mut body: &str) -> String {
+    let mut out = String::new();
+    while let Some((size, rest)) = body.split_once("\r\n") {
+        let size = usize::from_str_radix(size.trim(), 16).unwrap_or(0);
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        out.push_str(&rest[..size]);
+        body = rest[size..].trim_start_matches("\r\n");
+    }
+    out
+}
+
+// 进程的状态名称，Exited、Error 等带数据的状态取其名称
+pub fn state(detail: &Value) -> &str {
+    match &detail["state"] {
+        Value::String(s) => s,
+        Value::Object(map) => map.keys().next().map(String::as_str).unwrap_or(""),
+        _ => "",
+    }
+}
+
+pub fn pid_alive(pid: u64) -> bool {
+    pid > 0 && unsafe { libc::kill(pid as i32, 0) } == 0
+}
+
+// 父进程为 ppid 的进程，读取 /proc/[pid]/stat 的第 4 个字段，仅 Linux
+pub fn children_of(ppid: u64) -> Vec<u64> {
+    let mut pids = Vec::new();
+    for entry in std::fs::read_dir("/proc").into_iter().flatten().flatten() {
+        let Ok(pid) = entry.file_name().to_string_lossy().parse::<u64>() else {
+            continue;
+        };
+        let stat = read_to_string(&entry.path().join("stat"));
+        // comm 可能含有空格，从最后一个 ')' 之后开始解析
+        let Some((_, rest)) = stat.rsplit_once(')') else {
+            continue;
+        };
+        if rest.split_whitespace().nth(1).and_then(|p| p.parse().ok()) == Some(ppid) {
+            pids.push(pid);
+        }
+    }
+    pids.sort();
+    pids
+}
+
+pub fn read_to_string(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_default()
+}
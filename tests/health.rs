@@ -0,0 +1,35 @@
+// health_check.tcp 的地址解析和连接：主机名、IPv6 地址和无法解析的主机
+use oh_procd::process::health::connect_tcp;
+use std::net::TcpListener;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[tokio::test]
+async fn connects_to_localhost_by_name() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    // localhost 可能先解析为 ::1，连接失败后继续尝试 127.0.0.1
+    connect_tcp(&format!("localhost:{}", port), TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn connects_to_ipv6_literal() {
+    let listener = TcpListener::bind("[::1]:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    connect_tcp(&format!("[::1]:{}", port), TIMEOUT).await.unwrap();
+}
+
+#[tokio::test]
+async fn unresolvable_host_is_an_error() {
+    // .invalid 是保留的顶级域名，不会被解析
+    let err = connect_tcp("no-such-host.invalid:80", TIMEOUT).await.unwrap_err();
+    let msg = format!("{:#}", err);
+    assert!(msg.contains("no-such-host.invalid"), "{}", msg);
+}
+
+#[tokio::test]
+async fn closed_port_is_an_error() {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    assert!(connect_tcp(&format!("127.0.0.1:{}", port), TIMEOUT).await.is_err());
+}
@@ -0,0 +1,93 @@
+// 子进程统计：按父进程建立索引后逐层查找，结果和每层遍历全部进程的旧实现一致
+use oh_procd::process::stats::{
+    ProcInfo, build_children_index, get_child_pids_and_total_memory, get_child_pids_and_total_memory_by_scan,
+};
+use std::collections::HashMap;
+use sysinfo::Pid;
+
+// 模拟的进程，is_real 为 false 时表示线程
+struct FakeProc {
+    pid: Pid,
+    parent: Option<Pid>,
+    memory: u64,
+    cpu_usage: f32,
+    exists: bool,
+    is_real: bool,
+}
+
+impl ProcInfo for FakeProc {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+    fn parent(&self) -> Option<Pid> {
+        self.parent
+    }
+    fn memory(&self) -> u64 {
+        self.memory
+    }
+    fn exists(&self) -> bool {
+        self.exists
+    }
+    fn is_real(&self) -> bool {
+        self.is_real
+    }
+}
+
+// 进程树：1 为 init，10..=14 为被管理的进程，各有子进程和孙子进程，另有线程、已退出的进程和无关进程
+fn process_table() -> HashMap<Pid, FakeProc> {
+    let mut table = HashMap::new();
+    let mut add = |pid: usize, parent: Option<usize>, exists: bool, is_real: bool| {
+        let pid = Pid::from(pid);
+        table.insert(
+            pid,
+            FakeProc {
+                pid,
+                parent: parent.map(Pid::from),
+                memory: pid.as_u32() as u64 * 1024,
+                cpu_usage: pid.as_u32() as f32 / 100.0,
+                exists,
+                is_real,
+            },
+        );
+    };
+    add(1, None, true, true);
+    for managed in 10..=14 {
+        add(managed, Some(1), true, true);
+        for c in 0..4 {
+            let child = managed * 100 + c;
+            add(child, Some(managed), true, true);
+            add(child * 10, Some(child), true, true); // 孙子进程
+            add(child * 10 + 1, Some(child), true, false); // 线程，不计入
+        }
+        add(managed * 100 + 9, Some(managed), false, true); // 已退出，不计入
+    }
+    for other in 5000..5100 {
+        add(other, Some(1), true, true);
+    }
+    table
+}
+
+// 子进程的 pid（已排序）、内存之和以及 CPU 之和
+fn usage(table: &HashMap<Pid, FakeProc>, (mut pids, memory): (Vec<Pid>, u64)) -> (Vec<Pid>, u64, f32) {
+    pids.sort();
+    let cpu = pids.iter().map(|p| table[p].cpu_usage).sum();
+    (pids, memory, cpu)
+}
+
+#[test]
+fn children_index_matches_scan() {
+    let table = process_table();
+    let index = build_children_index(&table);
+    for managed in [10, 11, 12, 13, 14, 5000, 99999] {
+        let pid = Pid::from(managed);
+        let indexed = usage(&table, get_child_pids_and_total_memory(&table, &index, pid));
+        let scanned = usage(&table, get_child_pids_and_total_memory_by_scan(&table, pid));
+        assert_eq!(indexed, scanned, "pid {}", managed);
+    }
+
+    // 4 个子进程和 4 个孙子进程，不含线程和已退出的进程
+    let (pids, memory, _) = usage(&table, get_child_pids_and_total_memory(&table, &index, Pid::from(10)));
+    assert_eq!(pids.len(), 8);
+    let expected: u64 = (0..4).map(|c| (1000 + c + (1000 + c) * 10) as u64 * 1024).sum();
+    assert_eq!(memory, expected);
+}
@@ -0,0 +1,348 @@
+// supervise 循环的集成测试：启动真实的 oh-procd，管理 mock 进程，通过 API 观察状态变化
+mod common;
+
+use common::{Procd, mock_config, pid_alive, state};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// 测试程序作为 mock 进程启动时的入口，普通测试运行时什么都不做
+#[test]
+fn mock_process() {
+    common::run_mock();
+}
+
+#[test]
+fn exit_code_is_recorded() {
+    let procd = Procd::start(&[mock_config(
+        "once",
+        &[("MOCK_EXIT_AFTER", "100"), ("MOCK_EXIT_CODE", "3")],
+        &["restart: never"],
+    )]);
+    let detail = procd.wait_for("once", TIMEOUT, |p| state(p) == "Exited");
+    assert_eq!(detail["state"]["Exited"]["code"], 3);
+    assert_eq!(detail["start_count"], 1);
+}
+
+#[test]
+fn spawn_failure_is_not_retried_with_restart_never() {
+    let procd = Procd::start(&["  - name: missing\n    cmd: ./no-such-program\n    restart: never\n".to_string()]);
+    procd.wait_for("missing", TIMEOUT, |p| state(p) == "Error");
+    // 启动失败后的重试间隔为 1 秒，等待超过该时间后仍然只尝试过一次
+    std::thread::sleep(Duration::from_millis(1500));
+    let detail = procd.process("missing");
+    assert_eq!(state(&detail), "Error");
+    assert_eq!(detail["start_count"], 1, "{}", detail);
+}
+
+#[test]
+fn crash_loop_stops_after_max_restarts() {
+    let procd = Procd::start(&[mock_config(
+        "crash",
+        &[("MOCK_EXIT_AFTER", "50"), ("MOCK_EXIT_CODE", "1")],
+        &["max_restarts: 2", "restart_window: 60s"],
+    )]);
+    let detail = procd.wait_for("crash", TIMEOUT, |p| state(p) == "Error");
+    assert_eq!(detail["restarts_in_window"], 2, "{}", detail);
+    assert!(detail["state"]["Error"].as_str().unwrap().contains("giving up"));
+}
+
+#[test]
+fn ready_file_moves_starting_to_running() {
+    // 相对路径的 ready_file 基于 home，mock 进程的工作目录也是 home，即 oh-procd 的临时目录
+    let procd = Procd::start(&[mock_config(
+        "ready",
+        &[("MOCK_READY_FILE", "ready"), ("MOCK_READY_AFTER", "1000")],
+        &["ready_file: ready", "ready_timeout: 10s"],
+    )]);
+    procd.wait_for("ready", TIMEOUT, |p| state(p) == "Starting");
+    procd.wait_for("ready", TIMEOUT, |p| state(p) == "Running");
+    assert!(procd.path("ready").exists());
+}
+
+#[test]
+fn kill_stops_process() {
+    let procd = Procd::start(&[mock_config("sleeper", &[], &[])]);
+    let detail = procd.wait_for("sleeper", TIMEOUT, |p| state(p) == "Running");
+    let pid = detail["pid"].as_u64().unwrap();
+    assert!(pid_alive(pid));
+
+    let (status, body) = procd.post("/api/process/sleeper/kill");
+    assert_eq!(status, 200, "{}", body);
+    procd.wait_for("sleeper", TIMEOUT, |p| state(p) == "Killed");
+    assert!(!pid_alive(pid));
+
+    // 手动杀死后不会自动重启，可以通过 API 再次启动
+    let (status, body) = procd.post("/api/process/sleeper/start");
+    assert_eq!(status, 200, "{}", body);
+    let detail = procd.wait_for("sleeper", TIMEOUT, |p| state(p) == "Running");
+    assert_ne!(detail["pid"].as_u64().unwrap(), pid);
+}
+
+#[test]
+fn ignored_sigterm_escalates_to_sigkill() {
+    // mock 进程先忽略 SIGTERM 再创建 ready_file，Running 之后发送的 SIGTERM 一定会被忽略
+    let procd = Procd::start(&[mock_config(
+        "stubborn",
+        &[("MOCK_IGNORE_TERM", "1"), ("MOCK_READY_FILE", "ready")],
+        &["ready_file: ready", "term_timeout: 1s"],
+    )]);
+    let detail = procd.wait_for("stubborn", TIMEOUT, |p| state(p) == "Running");
+    let pid = detail["pid"].as_u64().unwrap();
+
+    let begin = Instant::now();
+    let (status, body) = procd.post("/api/process/stubborn/kill");
+    assert_eq!(status, 200, "{}", body);
+    procd.wait_for("stubborn", TIMEOUT, |p| state(p) == "Killed");
+    assert!(begin.elapsed() >= Duration::from_secs(1), "stopped before term_timeout");
+    assert!(!pid_alive(pid));
+}
+
+#[test]
+fn output_is_written_to_log_files() {
+    let procd = Procd::start(&[mock_config(
+        "talker",
+        &[("MOCK_STDOUT", "hello stdout"), ("MOCK_STDERR", "hello stderr")],
+        &["redirect_output: true", "output_dir: logs"],
+    )]);
+    let detail = procd.wait_for("talker", TIMEOUT, |p| {
+        let out = p["current_log_out"].as_str().unwrap_or("");
+        let err = p["current_log_err"].as_str().unwrap_or("");
+        common::read_to_string(out.as_ref()).contains("hello stdout")
+            && common::read_to_string(err.as_ref()).contains("hello stderr")
+    });
+    assert!(
+        detail["current_log_out"]
+            .as_str()
+            .unwrap()
+            .starts_with(procd.dir.to_str().unwrap())
+    );
+}
+
+#[test]
+fn stderr_tail_of_low_priority_is_trimmed_first() {
+    let procd = Procd::start_with(
+        "total_log_buffer_bytes: 30\n",
+        &[
+            mock_config(
+                "low",
+                &[("MOCK_STDERR", "low priority line")],
+                &["redirect_output: true", "output_dir: logs"],
+            ),
+            mock_config(
+                "high",
+                &[("MOCK_STDERR", "high priority line")],
+                &["log_buffer_priority: 1"],
+            ),
+        ],
+    );
+    // 日志文件中有这一行时，它已经先进入了 low 的 stderr_tail
+    procd.wait_for("low", TIMEOUT, |p| {
+        let err = p["current_log_err"].as_str().unwrap_or("");
+        common::read_to_string(err.as_ref()).contains("low priority line")
+    });
+    procd.wait_for("high", TIMEOUT, |p| p["stderr_tail"][0] == "high priority line");
+    // 两行共 35 字节，超过 30 时只裁剪优先级低的 low
+    procd.wait_for("low", TIMEOUT, |p| p["stderr_tail"].as_array().unwrap().is_empty());
+    let detail = procd.process("high");
+    assert_eq!(detail["stderr_tail"][0], "high priority line", "{}", detail);
+}
+
+#[test]
+fn next_run_is_shown_while_waiting_for_next() {
+    let procd = Procd::start(&[mock_config("job", &[("MOCK_EXIT_AFTER", "100")], &["next: 2s"])]);
+    // 先进入 Exited，之后开始等待 next 时才设置 next_run
+    let detail = procd.wait_for("job", TIMEOUT, |p| state(p) == "Exited" && p["next_run"].is_string());
+    assert!(detail["next_run"].as_str() > detail["exit_time"].as_str(), "{}", detail);
+
+    let detail = procd.wait_for("job", TIMEOUT, |p| p["start_count"] == 2);
+    assert!(detail["next_run"].is_null(), "{}", detail);
+}
+
+// 以下两个测试需要等待 watchdog 的 STUCK_TIMEOUT（60s）和一次检查间隔（5s），各运行约 70 秒
+const STUCK_WAIT: Duration = Duration::from_secs(70);
+
+#[test]
+fn spawn_failure_loop_is_flagged_stuck() {
+    let procd = Procd::start(&["  - name: missing\n    cmd: ./no-such-program\n".to_string()]);
+    let detail = procd.wait_for("missing", STUCK_WAIT + TIMEOUT, |p| p["stuck"] == true);
+    assert_eq!(state(&detail), "Error");
+    assert!(detail["stuck_secs"].as_u64().unwrap() >= 60, "{}", detail);
+}
+
+#[test]
+fn waiting_for_next_is_not_stuck() {
+    let procd = Procd::start(&[mock_config("job", &[("MOCK_EXIT_AFTER", "100")], &["next: 120s"])]);
+    procd.wait_for("job", TIMEOUT, |p| state(p) == "Exited");
+    // 按 next 等待期间处于 Exited，不计入未进入 Running 的时长
+    std::thread::sleep(STUCK_WAIT);
+    let detail = procd.process("job");
+    assert_eq!(state(&detail), "Exited", "{}", detail);
+    assert_eq!(detail["stuck"], false, "{}", detail);
+    assert_eq!(detail["start_count"], 1, "{}", detail);
+}
+
+#[test]
+fn rapid_start_kill_start_keeps_one_supervisor() {
+    let procd = Procd::start(&[mock_config("cycle", &[], &["auto_start: false"])]);
+    for _ in 0..5 {
+        for action in ["start", "kill", "start"] {
+            let (status, body) = procd.post(&format!("/api/process/cycle/{}", action));
+            assert!(status == 200 || status == 409, "{}: {} {}", action, status, body);
+        }
+    }
+    // 并发的启动请求只有一个能注册 supervise 循环
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| procd.post("/api/process/cycle/start"));
+        }
+    });
+
+    // 最后一次 start 可能在 kill 处理之前被当作已在运行，进程停在 Killed 时再启动一次
+    let detail = procd.wait_for("cycle", TIMEOUT, |p| {
+        if state(p) == "Killed" {
+            procd.post("/api/process/cycle/start");
+        }
+        state(p) == "Running"
+    });
+    let pid = detail["pid"].as_u64().unwrap();
+    // 之前被杀死的实例可能还在退出，最终只剩一个
+    let deadline = Instant::now() + TIMEOUT;
+    while procd.children() != [pid] {
+        assert!(Instant::now() < deadline, "children {:?}, expected [{}]", procd.children(), pid);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    // 只有一个 supervise 循环：kill 之后没有其他循环再把进程启动起来
+    let (status, body) = procd.post("/api/process/cycle/kill");
+    assert_eq!(status, 200, "{}", body);
+    procd.wait_for("cycle", TIMEOUT, |p| state(p) == "Killed");
+    std::thread::sleep(Duration::from_millis(1500));
+    let detail = procd.process("cycle");
+    assert_eq!(state(&detail), "Killed", "{}", detail);
+    assert!(procd.children().is_empty(), "{:?}", procd.children());
+    let (_, body) = procd.post("/api/process/cycle/kill");
+    assert_eq!(body, "process is already stopped", "control channel should be closed");
+}
+
+#[test]
+fn restarts_with_sigkill_do_not_leak_wait_threads() {
+    const RESTARTS: usize = 10;
+    // 忽略 SIGTERM，每次重启都在 term_timeout 后走 SIGKILL，并等待 wait 线程报告退出
+    let procd = Procd::start(&[mock_config(
+        "stubborn",
+        &[("MOCK_IGNORE_TERM", "1"), ("MOCK_READY_FILE", "ready")],
+        &["ready_file: ready", "term_timeout: 200ms"],
+    )]);
+    let mut pid = procd.wait_for("stubborn", TIMEOUT, |p| state(p) == "Running")["pid"]
+        .as_u64()
+        .unwrap();
+    let baseline = procd.threads();
+
+    for _ in 0..RESTARTS {
+        let (status, body) = procd.post("/api/process/stubborn/restart");
+        assert_eq!(status, 200, "{}", body);
+        let detail = procd.wait_for("stubborn", TIMEOUT, |p| state(p) == "Running" && p["pid"] != pid);
+        assert!(!pid_alive(pid), "old instance {} still alive", pid);
+        pid = detail["pid"].as_u64().unwrap();
+    }
+    assert_eq!(procd.children(), [pid]);
+
+    // 空闲的 blocking 线程 10 秒后退出；每次重启的 wait 线程都已结束时，线程数回到重启前的水平
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while procd.threads() > baseline + 2 {
+        assert!(
+            Instant::now() < deadline,
+            "{} threads after {} restarts, {} before",
+            procd.threads(),
+            RESTARTS,
+            baseline
+        );
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn partial_line_is_flushed_while_running() {
+    // 进程输出一行不带换行的 JSON 日志后不再输出，也不退出
+    let line = r#"{"level":"warn","msg":"waiting for input"}"#;
+    let procd = Procd::start(&[mock_config(
+        "idle",
+        &[("MOCK_STDERR_PARTIAL", line)],
+        &["log_json_passthrough: true"],
+    )]);
+    let detail = procd.wait_for("idle", TIMEOUT, |p| {
+        p["stderr_tail"].as_array().is_some_and(|t| !t.is_empty())
+    });
+    assert_eq!(state(&detail), "Running", "{}", detail);
+    assert_eq!(detail["stderr_tail"], serde_json::json!([line]), "{}", detail);
+    let (status, logs) = procd.get("/api/logs");
+    assert_eq!(status, 200);
+    assert!(logs.contains("waiting for input"), "{}", logs);
+}
+
+#[test]
+fn output_without_trailing_newline_is_kept_on_exit() {
+    let procd = Procd::start(&[mock_config(
+        "partial",
+        &[("MOCK_STDERR_PARTIAL", "partial"), ("MOCK_EXIT_AFTER", "100")],
+        &["restart: never", "redirect_output: true", "output_dir: logs"],
+    )]);
+    let detail = procd.wait_for("partial", TIMEOUT, |p| {
+        state(p) == "Exited" && p["stderr_tail"].as_array().is_some_and(|t| !t.is_empty())
+    });
+    assert_eq!(detail["stderr_tail"], serde_json::json!(["partial"]), "{}", detail);
+    let err = detail["current_log_err"].as_str().unwrap_or("");
+    assert!(
+        common::read_to_string(err.as_ref()).contains("partial"),
+        "log file {:?} has no partial line",
+        err
+    );
+}
+
+#[test]
+fn unresolvable_health_check_host_is_not_healthy() {
+    let procd = Procd::start(&[mock_config(
+        "checked",
+        &[],
+        &[
+            "health_check:",
+            "  tcp: no-such-host.invalid:80",
+            "  interval: 200ms",
+            "  timeout: 2s",
+            "  failures: 1000",
+        ],
+    )]);
+    // 解析失败按检查失败处理，进程继续运行，supervise 循环没有因此退出
+    let detail = procd.wait_for("checked", TIMEOUT, |p| p["health"] == "Degraded");
+    assert_eq!(state(&detail), "Running", "{}", detail);
+    assert_eq!(detail["supervisor_healthy"], true, "{}", detail);
+    std::thread::sleep(Duration::from_millis(1000));
+    let detail = procd.process("checked");
+    assert_eq!(detail["health"], "Degraded", "{}", detail);
+    assert_eq!(detail["start_count"], 1, "{}", detail);
+}
+
+#[test]
+fn relative_cmd_is_resolved_against_home() {
+    // home 和 oh-procd 的工作目录不同，./bin/app 只存在于 home 中
+    let home = std::env::temp_dir().join(format!("oh-procd-test-home-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&home);
+    std::fs::create_dir_all(home.join("bin")).unwrap();
+    let exe = std::env::current_exe().unwrap();
+    std::fs::copy(&exe, home.join("bin/app")).unwrap();
+
+    let config = mock_config("relative", &[], &[&format!("home: {}", home.display())])
+        .replace(&format!("cmd: {}", exe.display()), "cmd: ./bin/app");
+    let procd = Procd::start(&[config]);
+    let detail = procd.wait_for("relative", TIMEOUT, |p| state(p) == "Running");
+    assert_eq!(detail["start_count"], 1, "{}", detail);
+    assert!(!procd.path("bin/app").exists());
+    // Linux 上 exec 发生在切换工作目录之后，./bin/app 不解析也能启动，所以检查实际执行的 argv[0]
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", detail["pid"])).unwrap();
+    let argv0 = String::from_utf8_lossy(cmdline.split(|b| *b == 0).next().unwrap()).to_string();
+    assert_eq!(Path::new(&argv0), home.join("bin/app").canonicalize().unwrap());
+    drop(procd);
+    let _ = std::fs::remove_dir_all(&home);
+}